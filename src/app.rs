@@ -1,4 +1,7 @@
-use crate::test::{TestConfig, TestResult};
+use crate::bless;
+use crate::build::BuildStep;
+use crate::filter::TestFilter;
+use crate::test::{RunOptions, TestConfig, TestResult, TestStats};
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 // テスト実行の履歴を保存する構造体
@@ -10,9 +13,17 @@ pub struct TestHistory {
 }
 
 pub struct App {
+    /// Results currently shown, i.e. `all_results` narrowed by `filter`
     pub test_results: Vec<TestResult>,
+    pub all_results: Vec<TestResult>,
     pub config: TestConfig,
+    pub filter: TestFilter,
+    pub run_options: RunOptions,
+    pub editing_filter: bool,
+    pub filter_input: String,
     pub selected_test: usize,
+    /// Attempt of a retried test being viewed; `None` shows the final attempt
+    pub selected_attempt: Option<usize>,
     pub tab_index: usize,
     pub show_help: bool,
     pub release_mode: bool,
@@ -24,19 +35,46 @@ pub struct App {
     pub result_popup_visible: bool,
     pub result_popup_time: Option<Instant>,
     pub result_popup_message: String,
+    /// Update waiting for confirmation in the bless popup
+    pub pending_bless: Option<bless::Update>,
+    /// Build steps of the latest run, shown in the Build tab
+    pub build_steps: Vec<BuildStep>,
+    /// Selected entry of the Build tab (see `build_entries`)
+    pub selected_build: usize,
+    /// Why the suite teardown of the latest run failed, if it did
+    pub teardown_error: Option<String>,
 }
 
+/// A line of the Build tab: a build step, or one of its diagnostics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildEntry {
+    pub step: usize,
+    pub diagnostic: Option<usize>,
+}
+
+/// Index of the Build tab
+pub const BUILD_TAB: usize = 5;
+
+/// Number of tabs: Results, Stats, Diff, Commands, History and Build (the last one)
+pub const TAB_COUNT: usize = BUILD_TAB + 1;
+
 #[derive(PartialEq)]
 pub enum PopupType {
     None,
     RunTests,
     RunRelease,
     BuildToggle,
+    Bless,
     ResultNotification,
 }
 
 impl App {
-    pub fn new(test_results: Vec<TestResult>, config: TestConfig) -> Self {
+    pub fn new(
+        test_results: Vec<TestResult>,
+        config: TestConfig,
+        filter: TestFilter,
+        run_options: RunOptions,
+    ) -> Self {
         // 初期実行結果を履歴に追加
         let initial_history = TestHistory {
             timestamp: SystemTime::now()
@@ -47,10 +85,17 @@ impl App {
             release_mode: false,
         };
 
-        App {
-            test_results,
+        let filter_input = filter.pattern.clone().unwrap_or_default();
+        let mut app = App {
+            test_results: Vec::new(),
+            all_results: Vec::new(),
             config,
+            filter,
+            run_options,
+            editing_filter: false,
+            filter_input,
             selected_test: 0,
+            selected_attempt: None,
             tab_index: 0,
             show_help: false,
             release_mode: false,
@@ -62,17 +107,122 @@ impl App {
             result_popup_visible: false,
             result_popup_time: None,
             result_popup_message: String::new(),
+            pending_bless: None,
+            build_steps: Vec::new(),
+            selected_build: 0,
+            teardown_error: None,
+        };
+        app.set_results(test_results);
+        app
+    }
+
+    /// Replace the results and refresh the filtered view
+    pub fn set_results(&mut self, results: Vec<TestResult>) {
+        self.all_results = results;
+        self.refresh_filtered_results();
+    }
+
+    /// Show a blessed test as it would run against its new expected output
+    pub fn mark_blessed(&mut self, name: &str) {
+        if let Some(result) = self.all_results.iter_mut().find(|r| r.name == name) {
+            bless::mark_blessed(result);
+        }
+        self.refresh_filtered_results();
+    }
+
+    fn refresh_filtered_results(&mut self) {
+        self.test_results = self
+            .all_results
+            .iter()
+            .filter(|r| self.filter.matches(&r.name, &r.tags))
+            .cloned()
+            .collect();
+
+        if self.selected_test >= self.test_results.len() {
+            self.selected_test = 0;
         }
+        self.selected_attempt = None;
+    }
+
+    /// The selected result, showing the attempt chosen with `[` / `]`
+    pub fn current_result(&self) -> Option<TestResult> {
+        let result = self.test_results.get(self.selected_test)?;
+        Some(match self.selected_attempt {
+            Some(index) => result.with_attempt(index),
+            None => result.clone(),
+        })
+    }
+
+    pub fn next_attempt(&mut self) {
+        let Some(result) = self.test_results.get(self.selected_test) else {
+            return;
+        };
+        let count = result.attempts.len();
+        if count > 1 {
+            let current = self.selected_attempt.unwrap_or(count - 1);
+            self.selected_attempt = Some((current + 1) % count);
+        }
+    }
+
+    pub fn previous_attempt(&mut self) {
+        let Some(result) = self.test_results.get(self.selected_test) else {
+            return;
+        };
+        let count = result.attempts.len();
+        if count > 1 {
+            let current = self.selected_attempt.unwrap_or(count - 1);
+            self.selected_attempt = Some(if current > 0 { current - 1 } else { count - 1 });
+        }
+    }
+
+    /// The configuration narrowed down to the tests matching the current filter
+    pub fn filtered_config(&self) -> TestConfig {
+        let mut config = self.config.clone();
+        self.filter.apply(&mut config);
+        config
+    }
+
+    // フィルタ入力モードを開始
+    pub fn start_filter_edit(&mut self) {
+        self.editing_filter = true;
+        self.filter_input = self.filter.pattern.clone().unwrap_or_default();
+    }
+
+    // 入力中のフィルタを即座に反映する
+    pub fn push_filter_char(&mut self, c: char) {
+        self.filter_input.push(c);
+        self.filter.set_pattern(&self.filter_input.clone());
+        self.refresh_filtered_results();
+    }
+
+    pub fn pop_filter_char(&mut self) {
+        self.filter_input.pop();
+        self.filter.set_pattern(&self.filter_input.clone());
+        self.refresh_filtered_results();
+    }
+
+    pub fn finish_filter_edit(&mut self) {
+        self.editing_filter = false;
+    }
+
+    // 入力をすべて取り消してフィルタを解除
+    pub fn clear_filter_pattern(&mut self) {
+        self.editing_filter = false;
+        self.filter_input.clear();
+        self.filter.set_pattern("");
+        self.refresh_filtered_results();
     }
 
     pub fn next(&mut self) {
         if !self.test_results.is_empty() {
+            self.selected_attempt = None;
             self.selected_test = (self.selected_test + 1) % self.test_results.len();
         }
     }
 
     pub fn previous(&mut self) {
         if !self.test_results.is_empty() {
+            self.selected_attempt = None;
             self.selected_test = if self.selected_test > 0 {
                 self.selected_test - 1
             } else {
@@ -82,17 +232,69 @@ impl App {
     }
 
     pub fn next_tab(&mut self) {
-        self.tab_index = (self.tab_index + 1) % 5; // 5 tabs: Results, Stats, Diff, Commands, History
+        self.tab_index = (self.tab_index + 1) % TAB_COUNT;
     }
 
     pub fn previous_tab(&mut self) {
         self.tab_index = if self.tab_index > 0 {
             self.tab_index - 1
         } else {
-            4
+            TAB_COUNT - 1
         };
     }
 
+    /// Replace the build steps shown in the Build tab
+    pub fn set_build_steps(&mut self, steps: Vec<BuildStep>) {
+        self.build_steps = steps;
+        // 失敗したステップがあれば最初のエラーを選択する
+        let entries = self.build_entries();
+        self.selected_build = self
+            .build_steps
+            .iter()
+            .position(|step| !step.success)
+            .and_then(|failed| entries.iter().position(|e| e.step == failed))
+            .map(|index| {
+                let first_error = entries[index..].iter().position(|e| {
+                    e.diagnostic
+                        .is_some_and(|d| self.build_steps[e.step].diagnostics[d].level == "error")
+                });
+                index + first_error.unwrap_or(0)
+            })
+            .unwrap_or(0);
+    }
+
+    /// The lines of the Build tab: each step followed by its diagnostics
+    pub fn build_entries(&self) -> Vec<BuildEntry> {
+        self.build_steps
+            .iter()
+            .enumerate()
+            .flat_map(|(step, s)| {
+                std::iter::once(BuildEntry {
+                    step,
+                    diagnostic: None,
+                })
+                .chain((0..s.diagnostics.len()).map(move |d| BuildEntry {
+                    step,
+                    diagnostic: Some(d),
+                }))
+            })
+            .collect()
+    }
+
+    pub fn next_build_entry(&mut self) {
+        let count = self.build_entries().len();
+        if count > 0 {
+            self.selected_build = (self.selected_build + 1) % count;
+        }
+    }
+
+    pub fn previous_build_entry(&mut self) {
+        let count = self.build_entries().len();
+        if count > 0 {
+            self.selected_build = (self.selected_build + count - 1) % count;
+        }
+    }
+
     pub fn toggle_help(&mut self) {
         self.show_help = !self.show_help;
     }
@@ -106,24 +308,25 @@ impl App {
         }
     }
 
-    pub fn get_stats(&self) -> (usize, usize, f64) {
-        let total = self.test_results.len();
-        let passed = self.test_results.iter().filter(|r| r.success).count();
-        let pass_rate = if total > 0 {
-            (passed as f64 / total as f64) * 100.0
-        } else {
-            0.0
-        };
-
-        (passed, total, pass_rate)
+    pub fn get_stats(&self) -> TestStats {
+        TestStats::from_results(&self.test_results)
     }
 
     #[allow(clippy::type_complexity)]
-    pub fn get_command_details(&self) -> Option<(&str, &[String], Option<&String>, u128, bool, Option<&Vec<String>>)> {
+    pub fn get_command_details(
+        &self,
+    ) -> Option<(
+        &str,
+        &[String],
+        Option<&String>,
+        u128,
+        bool,
+        Option<&Vec<String>>,
+    )> {
         if self.test_results.is_empty() {
             return None;
         }
-        
+
         let test = &self.test_results[self.selected_test];
         Some((
             &test.command,
@@ -131,7 +334,7 @@ impl App {
             test.input.as_ref(),
             test.execution_time.as_millis(),
             test.is_release,
-            test.build_commands.as_ref()
+            test.build_commands.as_ref(),
         ))
     }
 
@@ -141,7 +344,7 @@ impl App {
                 .duration_since(UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs(),
-            test_results: self.test_results.clone(),
+            test_results: self.all_results.clone(),
             release_mode: self.release_mode,
         };
 
@@ -161,8 +364,9 @@ impl App {
             // 選択した履歴の結果を表示
             if let Some(history) = self.history.get(self.selected_history) {
                 // 一時的に履歴の結果を表示
-                self.test_results = history.test_results.clone();
+                let results = history.test_results.clone();
                 self.release_mode = history.release_mode;
+                self.set_results(results);
             }
         }
     }
@@ -177,19 +381,22 @@ impl App {
             // 選択した履歴の結果を表示
             if let Some(history) = self.history.get(self.selected_history) {
                 // 一時的に履歴の結果を表示
-                self.test_results = history.test_results.clone();
+                let results = history.test_results.clone();
                 self.release_mode = history.release_mode;
+                self.set_results(results);
             }
         }
     }
 
-    pub fn get_history_stats(&self) -> Vec<(u64, usize, usize, bool)> {
+    pub fn get_history_stats(&self) -> Vec<(u64, TestStats, bool)> {
         self.history
             .iter()
             .map(|h| {
-                let total = h.test_results.len();
-                let passed = h.test_results.iter().filter(|r| r.success).count();
-                (h.timestamp, passed, total, h.release_mode)
+                (
+                    h.timestamp,
+                    TestStats::from_results(&h.test_results),
+                    h.release_mode,
+                )
             })
             .collect()
     }
@@ -222,6 +429,26 @@ impl App {
     pub fn close_popup(&mut self) {
         self.show_popup = false;
         self.popup_type = PopupType::None;
+        self.pending_bless = None;
+    }
+
+    /// Ask to bless the selected test, or explain why it cannot be blessed
+    pub fn request_bless(&mut self, config_path: &std::path::Path) {
+        // 試行の表示に関係なく最終結果を使う
+        let Some(result) = self.test_results.get(self.selected_test) else {
+            return;
+        };
+        match bless::plan(config_path, &self.config, std::slice::from_ref(result)).pop() {
+            Some(update) => {
+                self.pending_bless = Some(update);
+                self.show_popup = true;
+                self.popup_type = PopupType::Bless;
+            }
+            None => self.show_result_popup(format!(
+                "Cannot bless {}:\nonly tests that failed because of their output can be blessed",
+                result.name
+            )),
+        }
     }
 
     // 結果ポップアップを表示
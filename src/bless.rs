@@ -0,0 +1,447 @@
+// src/bless.rs
+use crate::test::{self, TestConfig, TestResult, TestStatus};
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Where the new expected output of a test is written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// `expected_output` in the configuration file
+    Config(PathBuf),
+    /// The file referenced by `expected_output_file`
+    File(PathBuf),
+}
+
+impl Target {
+    pub fn path(&self) -> &Path {
+        match self {
+            Target::Config(path) | Target::File(path) => path,
+        }
+    }
+}
+
+/// A test whose expected output is replaced by its actual output
+#[derive(Debug, Clone)]
+pub struct Update {
+    pub name: String,
+    pub output: String,
+    pub target: Target,
+}
+
+/// Whether the actual output of a result can become its expected output
+///
+/// Only plain failures qualify: not timeouts, errors, xfail or tests that did not run, and not
+/// tests whose files, screen snapshots or dialog also failed, since blessing cannot fix those.
+pub fn is_blessable(result: &TestResult) -> bool {
+    result.status == TestStatus::Failed
+        && result.diff.is_some()
+        && result.failed_checks().is_empty()
+        && result.attempts.last().is_some_and(|a| !a.timed_out)
+}
+
+/// Update a blessed result as if it had run against its new expected output
+///
+/// Attempts whose output is the blessed one now pass; the status follows from them as for any
+/// other run, so a test that first failed with another output is reported as flaky.
+pub fn mark_blessed(result: &mut TestResult) {
+    let output = result.actual_output.trim().to_string();
+    for attempt in &mut result.attempts {
+        let only_output_failed = !attempt.timed_out
+            && attempt.file_checks.iter().all(|check| check.success)
+            && attempt.snapshot_checks.iter().all(|check| check.success)
+            && attempt.dialog.iter().all(|event| event.success);
+        if only_output_failed && attempt.actual_output.trim() == output {
+            attempt.success = true;
+            attempt.diff = None;
+        }
+    }
+    result.success = true;
+    result.diff = None;
+    result.status = match result.attempts.first() {
+        Some(first) if !first.success => TestStatus::Flaky,
+        _ => TestStatus::Passed,
+    };
+}
+
+/// The updates that would make the given failing results pass
+pub fn plan(config_path: &Path, config: &TestConfig, results: &[TestResult]) -> Vec<Update> {
+    results
+        .iter()
+        .filter(|r| is_blessable(r))
+        .filter_map(|result| {
+            let test_case = config.tests.iter().find(|t| t.name == result.name)?;
+            // 画面の期待値は expected_output ではないので書き換えない
+            if test_case.screen.is_some() {
+                return None;
+            }
+            Some(match &test_case.expected_output_file {
+                Some(path) => Update {
+                    name: result.name.clone(),
+                    output: result.actual_output.clone(),
+                    target: Target::File(path.clone()),
+                },
+                // 設定ファイルには末尾の改行を除いて書く（比較は前後の空白を無視する）
+                None => Update {
+                    name: result.name.clone(),
+                    output: result
+                        .actual_output
+                        .trim_end_matches(['\n', '\r'])
+                        .to_string(),
+                    target: Target::Config(config_path.to_path_buf()),
+                },
+            })
+        })
+        .collect()
+}
+
+/// Write the updates, editing the configuration file in place
+///
+/// Every edit of the configuration is made before anything is written, so an edit that fails
+/// leaves all the files as they were.
+pub fn apply(config_path: &Path, updates: &[Update]) -> Result<()> {
+    let mut source = None;
+    for update in updates {
+        if let Target::Config(_) = &update.target {
+            let current = match source.take() {
+                Some(source) => source,
+                None => std::fs::read_to_string(config_path).with_context(|| {
+                    format!("Failed to read config file: {}", config_path.display())
+                })?,
+            };
+            source = Some(set_expected_output(
+                config_path,
+                &current,
+                &update.name,
+                &update.output,
+            )?);
+        }
+    }
+
+    for update in updates {
+        if let Target::File(path) = &update.target {
+            std::fs::write(path, &update.output)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+    }
+    if let Some(source) = source {
+        std::fs::write(config_path, source)
+            .with_context(|| format!("Failed to write config file: {}", config_path.display()))?;
+    }
+    Ok(())
+}
+
+/// Replace the `expected_output` of a test in the source of a configuration file
+pub fn set_expected_output(
+    config_path: &Path,
+    source: &str,
+    name: &str,
+    output: &str,
+) -> Result<String> {
+    let extension = config_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+
+    let (updated, config): (String, TestConfig) = match extension.as_str() {
+        "toml" => {
+            let updated = set_toml_expected_output(source, name, output)?;
+            let config = toml::from_str(&updated)?;
+            (updated, config)
+        }
+        "yaml" | "yml" => {
+            let updated = set_yaml_expected_output(source, name, output)?;
+            let config = serde_yaml::from_str(&updated)?;
+            (updated, config)
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Unsupported configuration format: {}. Please use .yaml, .yml, or .toml files.",
+                extension
+            ))
+        }
+    };
+
+    // 書き換えた結果を読み直して、意図した値になっているか確かめる
+    let written = config.tests.iter().find(|t| t.name == name);
+    if written.and_then(|t| t.expected_output.as_deref()) != Some(output) {
+        return Err(anyhow::anyhow!(
+            "Could not update the expected output of test '{}' in {} safely; please edit it by hand",
+            name,
+            config_path.display()
+        ));
+    }
+    Ok(updated)
+}
+
+fn set_toml_expected_output(source: &str, name: &str, output: &str) -> Result<String> {
+    let mut document: toml_edit::DocumentMut = source.parse().context("TOML parse error")?;
+    let tests = document
+        .get_mut("tests")
+        .and_then(|item| item.as_array_of_tables_mut())
+        .ok_or_else(|| anyhow::anyhow!("`tests` must be written as [[tests]] tables"))?;
+    let table = tests
+        .iter_mut()
+        .find(|t| t.get("name").and_then(|n| n.as_str()) == Some(name))
+        .ok_or_else(|| anyhow::anyhow!("No test named {:?}", name))?;
+
+    // 既存の値の前後の空白やコメントを保つ
+    match table
+        .get_mut("expected_output")
+        .and_then(|item| item.as_value_mut())
+    {
+        Some(value) => {
+            let decor = value.decor().clone();
+            *value = output.into();
+            *value.decor_mut() = decor;
+        }
+        None => {
+            table.insert("expected_output", toml_edit::value(output));
+        }
+    }
+
+    Ok(document.to_string())
+}
+
+// YAML は書式を保ったまま編集できるライブラリがないので、該当する行だけを書き換える
+fn set_yaml_expected_output(source: &str, name: &str, output: &str) -> Result<String> {
+    let lines: Vec<&str> = source.lines().collect();
+    let name_index = test::find_test_line(source, name)
+        .ok_or_else(|| anyhow::anyhow!("Could not find test {:?} in the YAML source", name))?
+        - 1;
+    let key_indent = key_column(lines[name_index]);
+
+    // テスト項目の範囲（同じインデントのキーが続く間）
+    let mut start = name_index;
+    while start > 0
+        && !is_item_start(lines[start], key_indent)
+        && is_inside(lines[start - 1], key_indent)
+    {
+        start -= 1;
+    }
+    let mut end = name_index + 1;
+    while end < lines.len() && is_inside(lines[end], key_indent) {
+        end += 1;
+    }
+
+    let key_line = (start..end).find(|&i| {
+        key_column(lines[i]) == key_indent
+            && lines[i][key_indent..].starts_with("expected_output")
+            && lines[i][key_indent + "expected_output".len()..]
+                .trim_start()
+                .starts_with(':')
+    });
+
+    let mut updated: Vec<String> = Vec::with_capacity(lines.len() + 2);
+    match key_line {
+        Some(index) => {
+            // 値が複数行にわたる場合は、より深くインデントされた続きの行も置き換える
+            let mut value_end = index + 1;
+            while value_end < end
+                && (lines[value_end].trim().is_empty() || indent_of(lines[value_end]) > key_indent)
+            {
+                value_end += 1;
+            }
+            while value_end > index + 1 && lines[value_end - 1].trim().is_empty() {
+                value_end -= 1;
+            }
+
+            let colon = lines[index].find(':').expect("key line has a colon");
+            let prefix = &lines[index][..=colon];
+            updated.extend(lines[..index].iter().map(|l| l.to_string()));
+            updated.extend(render_yaml_value(prefix, key_indent, output));
+            updated.extend(lines[value_end..].iter().map(|l| l.to_string()));
+        }
+        None => {
+            let prefix = format!("{}expected_output:", " ".repeat(key_indent));
+            updated.extend(lines[..=name_index].iter().map(|l| l.to_string()));
+            updated.extend(render_yaml_value(&prefix, key_indent, output));
+            updated.extend(lines[name_index + 1..].iter().map(|l| l.to_string()));
+        }
+    }
+
+    let mut text = updated.join("\n");
+    if source.ends_with('\n') {
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// Lines of a YAML mapping entry `prefix value`, using a block scalar for multi-line values
+pub(crate) fn render_yaml_value(prefix: &str, key_indent: usize, output: &str) -> Vec<String> {
+    if !output.contains('\n') {
+        // JSON の文字列は YAML のダブルクォート文字列としても正しい
+        let quoted = serde_json::to_string(output).expect("strings serialize to JSON");
+        return vec![format!("{} {}", prefix, quoted)];
+    }
+
+    let chomping = if output.ends_with("\n\n") {
+        "+"
+    } else if output.ends_with('\n') {
+        ""
+    } else {
+        "-"
+    };
+    // 先頭行が空白で始まる場合はインデント幅を明示する
+    let indicator = if output.starts_with(' ') { "2" } else { "" };
+
+    let indent = " ".repeat(key_indent + 2);
+    let mut lines = vec![format!("{} |{}{}", prefix, indicator, chomping)];
+    let body = if chomping == "+" {
+        output.strip_suffix('\n').unwrap_or(output)
+    } else {
+        output.trim_end_matches('\n')
+    };
+    lines.extend(body.split('\n').map(|line| {
+        if line.is_empty() {
+            String::new()
+        } else {
+            format!("{}{}", indent, line)
+        }
+    }));
+    lines
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+// `- name:` のような行でも、キーが始まる列を返す
+fn key_column(line: &str) -> usize {
+    let indent = indent_of(line);
+    let rest = &line[indent..];
+    match rest.strip_prefix('-') {
+        Some(after) if after.starts_with(' ') => {
+            indent + 1 + (after.len() - after.trim_start_matches(' ').len())
+        }
+        _ => indent,
+    }
+}
+
+fn is_item_start(line: &str, key_indent: usize) -> bool {
+    indent_of(line) < key_indent && line.trim_start().starts_with('-')
+}
+
+// テスト項目の内側の行か（空行とコメントは内側として扱う）
+fn is_inside(line: &str, key_indent: usize) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.is_empty() || trimmed.starts_with('#') || indent_of(line) >= key_indent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::isolate::TempDir;
+
+    #[test]
+    fn render_yaml_value_quotes_one_line_and_uses_blocks_for_more() {
+        assert_eq!(
+            render_yaml_value("    expected_output:", 4, "say \"hi\""),
+            vec![r#"    expected_output: "say \"hi\"""#]
+        );
+        assert_eq!(
+            render_yaml_value("    expected_output:", 4, "a\n\nb"),
+            vec!["    expected_output: |-", "      a", "", "      b"]
+        );
+        assert_eq!(
+            render_yaml_value("  - name:", 4, " indented\n"),
+            vec!["  - name: |2", "       indented"]
+        );
+        assert_eq!(
+            render_yaml_value("k:", 0, "kept\n\n"),
+            vec!["k: |+", "  kept", ""]
+        );
+    }
+
+    #[test]
+    fn yaml_one_line_value_is_replaced() {
+        let source =
+            "tests:\n  - name: \"Echo\"\n    expected_output: 'old'\n    timeout_secs: 5\n";
+        assert_eq!(
+            set_yaml_expected_output(source, "Echo", "new").unwrap(),
+            "tests:\n  - name: \"Echo\"\n    expected_output: \"new\"\n    timeout_secs: 5\n"
+        );
+    }
+
+    #[test]
+    fn yaml_block_value_is_replaced() {
+        let source = r#"tests:
+  - name: "Lines"
+    expected_output: |
+      one
+
+      two
+    timeout_secs: 5
+"#;
+        assert_eq!(
+            set_yaml_expected_output(source, "Lines", "1\n2").unwrap(),
+            r#"tests:
+  - name: "Lines"
+    expected_output: |-
+      1
+      2
+    timeout_secs: 5
+"#
+        );
+    }
+
+    #[test]
+    fn yaml_missing_key_is_inserted_after_the_name() {
+        let source = "tests:\n- command: \"echo\"\n  name: Echo\n  screen:\n    keys: []\n";
+        assert_eq!(
+            set_yaml_expected_output(source, "Echo", "hi").unwrap(),
+            "tests:\n- command: \"echo\"\n  name: Echo\n  expected_output: \"hi\"\n  screen:\n    keys: []\n"
+        );
+    }
+
+    #[test]
+    fn yaml_edit_stays_inside_its_item() {
+        let source = r#"build:
+  release: false
+  steps:
+    - name: "Echo"
+      command: "true"
+tests:
+  - name: "First"
+    expected_output: "1"
+  - name: "Echo"
+    # the greeting
+    command: "echo"
+
+    # expected
+    expected_output: "old"
+  - name: "Last"
+    expected_output: "3"
+"#;
+        let updated = set_expected_output(Path::new("tests.yaml"), source, "Echo", "new").unwrap();
+        assert_eq!(updated, source.replace(r#""old""#, r#""new""#));
+    }
+
+    #[test]
+    fn apply_writes_nothing_when_an_edit_fails() {
+        let dir = TempDir::create("bless", &[]).unwrap();
+        let config_path = dir.path().join("tests.yaml");
+        let config =
+            "tests:\n  - name: \"Echo\"\n    command: \"echo\"\n    expected_output: \"old\"\n";
+        std::fs::write(&config_path, config).unwrap();
+        let output_path = dir.path().join("echo.out");
+        std::fs::write(&output_path, "old\n").unwrap();
+
+        let updates = [
+            Update {
+                name: "Echo from a file".to_string(),
+                output: "new\n".to_string(),
+                target: Target::File(output_path.clone()),
+            },
+            Update {
+                name: "Missing".to_string(),
+                output: "new".to_string(),
+                target: Target::Config(config_path.clone()),
+            },
+        ];
+        assert!(apply(&config_path, &updates).is_err());
+
+        assert_eq!(std::fs::read_to_string(&output_path).unwrap(), "old\n");
+        assert_eq!(std::fs::read_to_string(&config_path).unwrap(), config);
+    }
+}
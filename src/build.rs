@@ -0,0 +1,204 @@
+// src/build.rs
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Captured output of a build step: a pre-build command or the build of a `cargo_bin`
+///
+/// Setup and teardown commands are captured the same way, with the `Hook` kind.
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildStep {
+    pub kind: StepKind,
+    pub command: String,
+    /// Name of the step in `build.steps`
+    pub name: Option<String>,
+    /// Test the step belongs to; `None` for the global pre-build commands
+    pub test: Option<String>,
+    pub success: bool,
+    /// stdout, without the JSON messages of cargo
+    pub stdout: String,
+    pub stderr: String,
+    #[serde(rename = "duration_ms", serialize_with = "serialize_millis")]
+    pub duration: Duration,
+    /// Errors and warnings reported by cargo with `--message-format=json`
+    pub diagnostics: Vec<Diagnostic>,
+    /// The command was not run because its inputs did not change since its last success
+    pub cached: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepKind {
+    Build,
+    /// A setup or teardown command
+    Hook,
+}
+
+/// A compiler error or warning
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    /// `error` or `warning`
+    pub level: String,
+    pub message: String,
+    /// Location of the primary span, relative to the package
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// The message as the compiler prints it, with the source snippet
+    pub rendered: String,
+}
+
+fn serialize_millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+impl BuildStep {
+    /// A step skipped because its last successful run is still up to date
+    pub fn cached(command: &str, test: Option<&str>) -> Self {
+        BuildStep {
+            kind: StepKind::Build,
+            command: command.to_string(),
+            name: None,
+            test: test.map(String::from),
+            success: true,
+            stdout: String::new(),
+            stderr: String::new(),
+            duration: Duration::ZERO,
+            diagnostics: Vec::new(),
+            cached: true,
+        }
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.level == "error")
+    }
+
+    /// What to show when the step failed: the compiler errors, or the end of the output
+    pub fn failure_output(&self) -> String {
+        let errors: Vec<&str> = self.errors().map(|d| d.rendered.trim_end()).collect();
+        if !errors.is_empty() {
+            return errors.join("\n\n");
+        }
+        let output = if self.stderr.trim().is_empty() {
+            &self.stdout
+        } else {
+            &self.stderr
+        };
+        let lines: Vec<&str> = output.trim_end().lines().collect();
+        lines[lines.len().saturating_sub(20)..].join("\n")
+    }
+}
+
+/// Run a shell command as a build step, asking cargo for JSON diagnostics
+pub fn run_shell(command: &str, test: Option<&str>) -> Result<BuildStep> {
+    let mut process = Command::new("sh");
+    process.arg("-c").arg(with_json_messages(command));
+    run(process, command, test)
+}
+
+/// Run a command as a build step and capture its output
+pub fn run(mut process: Command, label: &str, test: Option<&str>) -> Result<BuildStep> {
+    let started = Instant::now();
+    let output = process
+        .output()
+        .with_context(|| format!("Failed to execute pre-build command: {}", label))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (stdout, diagnostics) = parse_cargo_messages(&stdout);
+    Ok(BuildStep {
+        kind: StepKind::Build,
+        command: label.to_string(),
+        name: None,
+        test: test.map(String::from),
+        success: output.status.success(),
+        stdout,
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        duration: started.elapsed(),
+        diagnostics,
+        cached: false,
+    })
+}
+
+// cargo build / check / clippy / test に --message-format=json を付ける（指定済みなら何もしない）
+fn with_json_messages(command: &str) -> String {
+    static CARGO: OnceLock<Regex> = OnceLock::new();
+    if command.contains("--message-format") {
+        return command.to_string();
+    }
+    let cargo = CARGO.get_or_init(|| {
+        Regex::new(r"(^|&&|\|\||;)(\s*cargo\s+(?:build|check|clippy|test|rustc)\b)")
+            .expect("valid regex")
+    });
+    cargo
+        .replace_all(command, "$1$2 --message-format=json")
+        .into_owned()
+}
+
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<CompilerMessage>,
+}
+
+#[derive(Deserialize)]
+struct CompilerMessage {
+    level: String,
+    message: String,
+    rendered: Option<String>,
+    #[serde(default)]
+    spans: Vec<Span>,
+}
+
+#[derive(Deserialize)]
+struct Span {
+    file_name: String,
+    line_start: usize,
+    column_start: usize,
+    is_primary: bool,
+}
+
+// JSON のメッセージから診断を取り出し、それ以外の行はそのまま残す
+fn parse_cargo_messages(stdout: &str) -> (String, Vec<Diagnostic>) {
+    let mut rest = String::new();
+    let mut diagnostics = Vec::new();
+    for line in stdout.lines() {
+        let message = line
+            .starts_with('{')
+            .then(|| serde_json::from_str::<CargoMessage>(line).ok())
+            .flatten();
+        let Some(message) = message else {
+            rest.push_str(line);
+            rest.push('\n');
+            continue;
+        };
+        let Some(compiler) = message
+            .message
+            .filter(|_| message.reason == "compiler-message")
+        else {
+            continue;
+        };
+        // 「aborting due to ...」のような要約は一覧に出さない
+        let summary = compiler.spans.is_empty() && compiler.message.starts_with("aborting due to");
+        if (compiler.level != "error" && compiler.level != "warning") || summary {
+            continue;
+        }
+        let span = compiler.spans.iter().find(|s| s.is_primary);
+        diagnostics.push(Diagnostic {
+            level: compiler.level,
+            rendered: compiler
+                .rendered
+                .unwrap_or_else(|| compiler.message.clone()),
+            message: compiler.message,
+            file: span.map(|s| s.file_name.clone()),
+            line: span.map(|s| s.line_start),
+            column: span.map(|s| s.column_start),
+        });
+    }
+    (rest, diagnostics)
+}
@@ -0,0 +1,384 @@
+// src/cache.rs
+use crate::build::{self, BuildStep};
+use crate::watch;
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+/// Where the last successful runs of the build steps with `inputs` are kept
+pub const CACHE_FILE: &str = ".yamori/build-cache.json";
+
+/// Version of the cache file; a cache written by another version is ignored
+const CACHE_VERSION: u32 = 1;
+
+/// Decides which build steps need to run
+///
+/// A command runs at most once per run, and a command with `inputs` is skipped while the
+/// files matching them are unchanged since its last success. It is shared by the threads
+/// running the build steps; a thread asking for a command that another one is running waits
+/// for its result.
+#[derive(Debug)]
+pub struct BuildCache {
+    state: Mutex<CacheState>,
+    /// Signaled whenever a command stops running
+    finished: Condvar,
+    file: PathBuf,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: BTreeMap<String, CacheEntry>,
+    /// Commands running or succeeded during this run
+    commands: HashMap<String, Progress>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Progress {
+    Running,
+    Succeeded,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    steps: BTreeMap<String, CacheEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    /// Hash of the input globs and of the files matching them
+    inputs: String,
+    finished: String,
+}
+
+impl BuildCache {
+    /// Read the cache of the current directory; a missing or unreadable cache is empty
+    pub fn load() -> Self {
+        Self::open(Path::new(CACHE_FILE))
+    }
+
+    /// Read the cache kept in `file`; a missing or unreadable cache is empty
+    pub fn open(file: &Path) -> Self {
+        let entries = std::fs::read_to_string(file)
+            .ok()
+            .and_then(|text| serde_json::from_str::<CacheFile>(&text).ok())
+            .filter(|file| file.version == CACHE_VERSION)
+            .map(|file| file.steps)
+            .unwrap_or_default();
+        BuildCache {
+            state: Mutex::new(CacheState {
+                entries,
+                commands: HashMap::new(),
+            }),
+            finished: Condvar::new(),
+            file: file.to_path_buf(),
+        }
+    }
+
+    /// Run `cmd` (a command with its template expanded) unless it can be skipped
+    ///
+    /// Returns `None` when the command already succeeded during this run, and a cached step
+    /// when the files matching `inputs` are unchanged. While another thread runs the same
+    /// command, waits for it; if it failed, the command runs again.
+    pub fn run(
+        &self,
+        cmd: &str,
+        inputs: &[String],
+        outputs: &[PathBuf],
+        test: Option<&str>,
+    ) -> Result<Option<BuildStep>> {
+        // 確かめてから実行中の印を付けるまで同じロックの中で行う
+        let mut state = self.lock();
+        loop {
+            match state.commands.get(cmd) {
+                Some(Progress::Succeeded) => return Ok(None),
+                Some(Progress::Running) => {
+                    state = self.finished.wait(state).unwrap_or_else(|e| e.into_inner());
+                }
+                None => break,
+            }
+        }
+        state.commands.insert(cmd.to_string(), Progress::Running);
+        drop(state);
+
+        let mut running = Running {
+            cache: self,
+            cmd,
+            succeeded: false,
+        };
+        let step = self.run_step(cmd, inputs, outputs, test)?;
+        running.succeeded = step.success;
+        Ok(Some(step))
+    }
+
+    // キャッシュを確かめて、必要ならコマンドを実行する
+    fn run_step(
+        &self,
+        cmd: &str,
+        inputs: &[String],
+        outputs: &[PathBuf],
+        test: Option<&str>,
+    ) -> Result<BuildStep> {
+        // 実行前の入力のハッシュを記録する（実行中に変更されたら次回もう一度実行する）
+        let hash = if inputs.is_empty() {
+            None
+        } else {
+            Some(hash_inputs(inputs)?)
+        };
+        let up_to_date = hash.as_ref().is_some_and(|hash| {
+            self.lock()
+                .entries
+                .get(cmd)
+                .is_some_and(|e| &e.inputs == hash)
+                && outputs.iter().all(|path| path.exists())
+        });
+        if up_to_date {
+            return Ok(BuildStep::cached(cmd, test));
+        }
+
+        // コマンドの実行中はロックしない
+        let result = build::run_shell(cmd, test)?;
+        let mut state = self.lock();
+        if let Some(hash) = hash {
+            if result.success {
+                state.entries.insert(
+                    cmd.to_string(),
+                    CacheEntry {
+                        inputs: hash,
+                        finished: chrono::Utc::now().to_rfc3339(),
+                    },
+                );
+            } else {
+                state.entries.remove(cmd);
+            }
+            // キャッシュが書けなくてもビルドの結果は変わらないので無視する
+            let _ = save(&self.file, &state.entries);
+        }
+        Ok(result)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        // ロック中に panic したスレッドがあっても状態は壊れていない
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// 実行中の印を、結果にかかわらず（エラーや panic でも）外して、待っているスレッドを起こす
+struct Running<'a> {
+    cache: &'a BuildCache,
+    cmd: &'a str,
+    succeeded: bool,
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        let mut state = self.cache.lock();
+        if self.succeeded {
+            state
+                .commands
+                .insert(self.cmd.to_string(), Progress::Succeeded);
+        } else {
+            state.commands.remove(self.cmd);
+        }
+        self.cache.finished.notify_all();
+    }
+}
+
+fn save(path: &Path, entries: &BTreeMap<String, CacheEntry>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = CacheFile {
+        version: CACHE_VERSION,
+        steps: entries.clone(),
+    };
+    std::fs::write(path, serde_json::to_string_pretty(&file)?)?;
+    Ok(())
+}
+
+// グロブに一致するファイルのパスと内容からハッシュを計算する
+fn hash_inputs(globs: &[String]) -> Result<String> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob).with_context(|| format!("Invalid input glob: {}", glob))?);
+    }
+    let set = builder.build()?;
+
+    // ワイルドカードのないグロブはそのファイルだけを見る
+    let (literals, patterns): (Vec<&String>, Vec<&String>) = globs
+        .iter()
+        .partition(|glob| !glob.contains(['*', '?', '[', '{']));
+    let mut files: Vec<PathBuf> = literals
+        .iter()
+        .map(PathBuf::from)
+        .filter(|path| path.is_file())
+        .collect();
+    let mut bases: Vec<PathBuf> = patterns.iter().map(|g| watch::glob_base(g)).collect();
+    bases.sort();
+    bases.dedup();
+    for base in &bases {
+        let dir = if base.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            base.as_path()
+        };
+        collect_files(dir, base, &mut files);
+    }
+    files.retain(|path| set.is_match(path));
+    files.sort();
+    files.dedup();
+
+    let mut hasher = Sha256::new();
+    for glob in globs {
+        hasher.update(glob.as_bytes());
+        hasher.update([0]);
+    }
+    for path in &files {
+        let contents = std::fs::read(path)
+            .with_context(|| format!("Failed to read input file: {}", path.display()))?;
+        hasher.update(path.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// ファイルを再帰的に集める（パスはカレントディレクトリからの相対パス）
+fn collect_files(dir: &Path, relative: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let path = relative.join(&name);
+        if entry.path().is_dir() {
+            // ビルド成果物と隠しディレクトリは探さない
+            let hidden = name.to_string_lossy().starts_with('.');
+            if !hidden && name != "target" {
+                collect_files(&entry.path(), &path, files);
+            }
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::isolate::TempDir;
+
+    fn write(path: &Path, contents: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    fn glob(dir: &TempDir, pattern: &str) -> String {
+        dir.path().join(pattern).display().to_string()
+    }
+
+    #[test]
+    fn hash_changes_with_the_contents_of_the_inputs() {
+        let dir = TempDir::create("cache", &[]).unwrap();
+        let input = dir.path().join("src/main.rs");
+        write(&input, "fn main() {}");
+        let globs = [glob(&dir, "src/**/*.rs")];
+
+        let before = hash_inputs(&globs).unwrap();
+        write(&input, "fn main() { run() }");
+        assert_ne!(hash_inputs(&globs).unwrap(), before);
+        write(&input, "fn main() {}");
+        assert_eq!(hash_inputs(&globs).unwrap(), before);
+    }
+
+    #[test]
+    fn literal_inputs_only_see_their_file() {
+        let dir = TempDir::create("cache", &[]).unwrap();
+        write(&dir.path().join("a.txt"), "a");
+        let literal = [glob(&dir, "a.txt")];
+        let pattern = [glob(&dir, "*.txt")];
+        let (literal_before, pattern_before) = (
+            hash_inputs(&literal).unwrap(),
+            hash_inputs(&pattern).unwrap(),
+        );
+
+        write(&dir.path().join("b.txt"), "b");
+        assert_eq!(hash_inputs(&literal).unwrap(), literal_before);
+        assert_ne!(hash_inputs(&pattern).unwrap(), pattern_before);
+    }
+
+    #[test]
+    fn hidden_and_target_directories_are_not_inputs() {
+        let dir = TempDir::create("cache", &[]).unwrap();
+        write(&dir.path().join("src/lib.rs"), "");
+        let globs = [glob(&dir, "**/*.rs")];
+        let before = hash_inputs(&globs).unwrap();
+
+        write(&dir.path().join(".git/hooks/pre-commit.rs"), "");
+        write(&dir.path().join("target/debug/build.rs"), "");
+        assert_eq!(hash_inputs(&globs).unwrap(), before);
+
+        write(&dir.path().join("src/bin/tool.rs"), "");
+        assert_ne!(hash_inputs(&globs).unwrap(), before);
+    }
+
+    #[test]
+    fn unchanged_inputs_skip_the_command() {
+        let dir = TempDir::create("cache", &[]).unwrap();
+        let file = dir.path().join("build-cache.json");
+        let input = dir.path().join("input.txt");
+        write(&input, "1");
+        let inputs = [input.display().to_string()];
+        let cmd = "true";
+
+        let cache = BuildCache::open(&file);
+        let step = cache.run(cmd, &inputs, &[], None).unwrap().unwrap();
+        assert!(step.success && !step.cached);
+        // 同じ実行の中では二度目は何もしない
+        assert!(cache.run(cmd, &inputs, &[], None).unwrap().is_none());
+
+        let step = BuildCache::open(&file)
+            .run(cmd, &inputs, &[], None)
+            .unwrap()
+            .unwrap();
+        assert!(step.cached);
+
+        write(&input, "2");
+        let step = BuildCache::open(&file)
+            .run(cmd, &inputs, &[], None)
+            .unwrap()
+            .unwrap();
+        assert!(!step.cached);
+
+        // 出力がなくなったら入力が同じでも実行する
+        let missing = [dir.path().join("missing.out")];
+        let step = BuildCache::open(&file)
+            .run(cmd, &inputs, &missing, None)
+            .unwrap()
+            .unwrap();
+        assert!(!step.cached);
+    }
+
+    #[test]
+    fn a_command_runs_once_when_asked_from_two_threads() {
+        let dir = TempDir::create("cache", &[]).unwrap();
+        let log = dir.path().join("runs.log");
+        let cmd = format!("sleep 0.3; echo run >> '{}'", log.display());
+        let cache = BuildCache::open(&dir.path().join("build-cache.json"));
+
+        let steps: Vec<Option<BuildStep>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..2)
+                .map(|_| scope.spawn(|| cache.run(&cmd, &[], &[], None).unwrap()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert_eq!(steps.iter().filter(|step| step.is_some()).count(), 1);
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "run\n");
+    }
+}
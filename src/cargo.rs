@@ -0,0 +1,192 @@
+// src/cargo.rs
+use crate::build::{self, BuildStep};
+use crate::test::CargoBin;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The parts of `cargo metadata` that yamori uses
+#[derive(Debug, Clone, Deserialize)]
+pub struct Metadata {
+    /// Members of the workspace (dependencies are not listed)
+    pub packages: Vec<Package>,
+    pub workspace_root: PathBuf,
+    pub target_directory: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub manifest_path: PathBuf,
+    pub targets: Vec<Target>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Target {
+    pub name: String,
+    pub kind: Vec<String>,
+}
+
+impl Package {
+    /// Directory containing the package's Cargo.toml
+    pub fn root(&self) -> &Path {
+        self.manifest_path.parent().unwrap_or(Path::new("."))
+    }
+
+    pub fn binaries(&self) -> impl Iterator<Item = &Target> {
+        self.targets
+            .iter()
+            .filter(|t| t.kind.iter().any(|k| k == "bin"))
+    }
+}
+
+/// Run `cargo metadata` for the workspace of the manifest, or of the current directory
+pub fn metadata(manifest_path: Option<&Path>) -> Result<Metadata> {
+    let mut command = Command::new("cargo");
+    command.args(["metadata", "--no-deps", "--format-version", "1"]);
+    if let Some(path) = manifest_path {
+        command.arg("--manifest-path").arg(path);
+    }
+
+    let output = command
+        .output()
+        .context("Failed to run `cargo metadata`; is cargo installed?")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "`cargo metadata` failed:\n{}",
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ));
+    }
+
+    serde_json::from_slice(&output.stdout).context("Failed to parse the output of `cargo metadata`")
+}
+
+/// Resolves `cargo_bin` targets to executables, building each one once per run
+#[derive(Debug, Default)]
+pub struct BinaryCache {
+    metadata: HashMap<Option<PathBuf>, Metadata>,
+    built: HashMap<(CargoBin, bool), PathBuf>,
+}
+
+impl BinaryCache {
+    /// Build the binary for the build mode and return the path of the executable
+    pub fn resolve(
+        &mut self,
+        cargo_bin: &CargoBin,
+        release: bool,
+        test: Option<&str>,
+        on_step: &mut dyn FnMut(&BuildStep),
+    ) -> Result<PathBuf> {
+        let key = (cargo_bin.clone(), release);
+        if let Some(path) = self.built.get(&key) {
+            return Ok(path.clone());
+        }
+
+        let manifest_path = cargo_bin.manifest_path.clone();
+        let metadata = match self.metadata.entry(manifest_path.clone()) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(metadata(manifest_path.as_deref())?)
+            }
+        };
+        let (package, bin) = find_binary(metadata, cargo_bin)?;
+
+        let mut args = vec![
+            "build".to_string(),
+            "--package".to_string(),
+            package.name.clone(),
+            "--bin".to_string(),
+            bin.name.clone(),
+        ];
+        if let Some(path) = &manifest_path {
+            args.push("--manifest-path".to_string());
+            args.push(path.display().to_string());
+        }
+        if release {
+            args.push("--release".to_string());
+        }
+        let mut command = Command::new("cargo");
+        command.args(&args).arg("--message-format=json");
+        let step = build::run(command, &format!("cargo {}", args.join(" ")), test)?;
+        on_step(&step);
+        if !step.success {
+            return Err(anyhow::anyhow!(
+                "`cargo build` of {} failed:\n\n{}",
+                bin.name,
+                step.failure_output()
+            ));
+        }
+
+        let path = metadata
+            .target_directory
+            .join(if release { "release" } else { "debug" })
+            .join(format!("{}{}", bin.name, std::env::consts::EXE_SUFFIX));
+        self.built.insert(key, path.clone());
+        Ok(path)
+    }
+}
+
+// package / bin が省略された場合は、ワークスペースに一つしかなければそれを使う
+fn find_binary<'a>(
+    metadata: &'a Metadata,
+    cargo_bin: &CargoBin,
+) -> Result<(&'a Package, &'a Target)> {
+    let names = |packages: &[&Package]| {
+        packages
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let candidates: Vec<&Package> = metadata
+        .packages
+        .iter()
+        .filter(|p| {
+            cargo_bin
+                .package
+                .as_ref()
+                .is_none_or(|name| &p.name == name)
+        })
+        .filter(|p| match &cargo_bin.bin {
+            Some(bin) => p.binaries().any(|b| &b.name == bin),
+            None => p.binaries().next().is_some(),
+        })
+        .collect();
+    let package = match candidates.as_slice() {
+        [package] => *package,
+        [] => {
+            return Err(anyhow::anyhow!(
+                "No binary matching {} in the Cargo workspace at {}",
+                cargo_bin.label(),
+                metadata.workspace_root.display()
+            ))
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "{} matches several packages ({}); set `package`",
+                cargo_bin.label(),
+                names(&candidates)
+            ))
+        }
+    };
+
+    let binaries: Vec<&Target> = package
+        .binaries()
+        .filter(|b| cargo_bin.bin.as_ref().is_none_or(|name| &b.name == name))
+        .collect();
+    match binaries.as_slice() {
+        [bin] => Ok((package, bin)),
+        _ => Err(anyhow::anyhow!(
+            "Package {} has several binaries ({}); set `bin`",
+            package.name,
+            binaries
+                .iter()
+                .map(|b| b.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
@@ -1,79 +1,1181 @@
 // src/cli.rs
-use crate::test::{self, TestResult};
+use crate::bless;
+use crate::build::{BuildStep, StepKind};
+use crate::cargo;
+use crate::error::ErrorKind;
+use crate::filter::TestFilter;
+use crate::output::{Formatter, GithubFormatter, OutputFormat, RunInfo, TapFormatter, Verbosity};
+use crate::record;
+use crate::report::{self, ReportSpec};
+use crate::scaffold;
+use crate::test::{
+    self, DialogEvent, DiffLine, RunOptions, RunOutcome, TestCase, TestConfig, TestEvent,
+    TestResult, TestStats, TestStatus,
+};
+use crate::watch::{self, WatchOptions, Watcher};
 use anyhow::{Context, Result};
 use crossterm::style::Stylize;
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+/// Options controlling how a headless run is reported and what happens after it
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    /// Offer to write the actual output of failing tests back as their expected output
+    pub bless: bool,
+    /// Do not ask for confirmation before blessing
+    pub assume_yes: bool,
+    pub format: OutputFormat,
+    /// Exit successfully even if tests failed
+    pub no_fail: bool,
+    pub verbosity: Verbosity,
+    pub reports: Vec<ReportSpec>,
+}
 
 /// Run tests in CLI mode and print results to stdout
-pub fn run_cli(config_path: PathBuf) -> Result<()> {
+pub fn run_cli(
+    config_path: PathBuf,
+    filter: &TestFilter,
+    options: &RunOptions,
+    output: &OutputOptions,
+) -> Result<()> {
+    let config = load_filtered_config(&config_path, filter)?;
+    let outcome = run_config(&config_path, &config, filter, options, output, output.bless)?;
+
+    // Return success only if no test or suite teardown failed (skipped and xfailed tests are fine)
+    if outcome.has_failures() && !output.no_fail {
+        Err(ErrorKind::TestsFailed.into())
+    } else {
+        Ok(())
+    }
+}
+
+fn load_filtered_config(config_path: &PathBuf, filter: &TestFilter) -> Result<TestConfig> {
     // Load and parse the configuration
-    let config = test::load_config(&config_path)
-        .with_context(|| format!("failed to load config from `{}`", config_path.display()))?;
+    let mut config = test::load_config(config_path)
+        .with_context(|| format!("failed to load config from `{}`", config_path.display()))
+        .context(ErrorKind::ConfigInvalid)?;
+    filter.apply(&mut config);
+    Ok(config)
+}
+
+/// Run the tests of an already loaded configuration, print them and write the reports
+///
+/// With `bless`, the failing outputs are offered for blessing before the reports are written.
+fn run_config(
+    config_path: &Path,
+    config: &TestConfig,
+    filter: &TestFilter,
+    options: &RunOptions,
+    output: &OutputOptions,
+    bless: bool,
+) -> Result<RunOutcome> {
+    let focused = config.tests.iter().filter(|t| t.is_only()).count();
+    let run = RunInfo {
+        config_path,
+        filter,
+        selected: config.tests.len(),
+        focused,
+        planned: test::planned_count(config),
+    };
+
+    let mut formatters = formatters_for(output.format, output.verbosity);
+    for formatter in &mut formatters {
+        formatter.start(&run);
+    }
+
+    // Run all tests, streaming events to the formatters and the jsonl reports
+    let mut event_log = report::EventLog::open(&output.reports, run.planned)?;
+    let mut outcome = test::run_tests_with(config, options, |event| {
+        for formatter in &mut formatters {
+            formatter.event(event);
+        }
+        event_log.record(event);
+    })?;
+
+    for formatter in &mut formatters {
+        formatter.finish(&outcome);
+    }
+
+    // 期待出力を書き換えたテストは、書き換えた後の期待出力で判定し直して報告する
+    if bless {
+        let blessed = bless_results(config_path, config, &outcome.results, output.assume_yes)?;
+        for result in &mut outcome.results {
+            if blessed.contains(&result.name) {
+                bless::mark_blessed(result);
+            }
+        }
+    }
+
+    // レポートはテストの成否に関係なく書き出す
+    event_log.finish(&outcome)?;
+    report::write_reports(&output.reports, &outcome)?;
+    for spec in &output.reports {
+        eprintln!(
+            "Wrote {} report to {}",
+            spec.format.name(),
+            spec.path.display()
+        );
+    }
+
+    Ok(outcome)
+}
+
+/// Show the new expected outputs of the failing tests and write them after confirmation
+///
+/// Returns the names of the tests that were updated.
+fn bless_results(
+    config_path: &Path,
+    config: &TestConfig,
+    results: &[TestResult],
+    assume_yes: bool,
+) -> Result<Vec<String>> {
+    let updates = bless::plan(config_path, config, results);
+    if updates.is_empty() {
+        eprintln!("\nNothing to bless: no test failed because of its output.");
+        return Ok(Vec::new());
+    }
+
+    eprintln!("\n=== Bless ===");
+    for update in &updates {
+        eprintln!(
+            "\n{} -> {}",
+            update.name.clone().bold(),
+            update.target.path().display()
+        );
+        for line in results
+            .iter()
+            .filter(|r| r.name == update.name)
+            .flat_map(|r| r.diff.iter().flatten())
+        {
+            eprintln!("{}", diff_line(line));
+        }
+    }
+
+    if !assume_yes {
+        if !std::io::stdin().is_terminal() {
+            eprintln!("\nNot writing anything: stdin is not a terminal. Use --yes to bless without asking.");
+            return Ok(Vec::new());
+        }
+        eprint!(
+            "\nWrite the actual output as the expected output of {} test(s)? [y/N] ",
+            updates.len()
+        );
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            eprintln!("Nothing written.");
+            return Ok(Vec::new());
+        }
+    }
+
+    bless::apply(config_path, &updates)?;
+    eprintln!("{} {} test(s) updated", "BLESSED".green(), updates.len());
+    Ok(updates.into_iter().map(|u| u.name).collect())
+}
+
+/// Run the tests, then again every time a watched file changes, until interrupted
+pub fn watch_cli(
+    config_path: PathBuf,
+    filter: &TestFilter,
+    options: &RunOptions,
+    output: &OutputOptions,
+    watch_options: &WatchOptions,
+) -> Result<()> {
+    let mut watcher = Watcher::new(&config_path, None, watch_options)?;
+    let mut previous_config: Option<TestConfig> = None;
+    let mut failed = HashSet::new();
+    let mut changed: Vec<PathBuf> = Vec::new();
+
+    loop {
+        // 設定ファイルの誤りやビルドの失敗では終了せず、次の変更を待つ
+        match load_filtered_config(&config_path, filter) {
+            Ok(config) => {
+                watcher.update(&config_path, Some(&config), watch_options)?;
+
+                let first = if watch_options.affected_first && previous_config.is_some() {
+                    watch::affected_tests(&config, previous_config.as_ref(), &changed, &failed)
+                } else {
+                    Vec::new()
+                };
+
+                let mut rerun_all = true;
+                if !first.is_empty() && first.len() < config.tests.len() {
+                    println!(
+                        "{} running {} affected or previously failed test(s) first",
+                        "WATCH".cyan(),
+                        first.len()
+                    );
+                    match run_config(
+                        &config_path,
+                        &watch::subset(&config, &first),
+                        filter,
+                        options,
+                        output,
+                        false,
+                    ) {
+                        Ok(outcome) => {
+                            watch::update_failed(&mut failed, &outcome.results);
+                            rerun_all = !outcome.has_failures();
+                        }
+                        Err(e) => {
+                            eprintln!("Error: {:?}", e);
+                            rerun_all = false;
+                        }
+                    }
+                    if rerun_all {
+                        println!("\n{} running all tests", "WATCH".cyan());
+                    }
+                }
+
+                if rerun_all {
+                    match run_config(&config_path, &config, filter, options, output, false) {
+                        Ok(outcome) => watch::update_failed(&mut failed, &outcome.results),
+                        Err(e) => eprintln!("Error: {:?}", e),
+                    }
+                }
+                previous_config = Some(config);
+            }
+            Err(e) => eprintln!("Error: {:?}", e),
+        }
+
+        println!("\n{} waiting for changes (Ctrl+C to stop)", "WATCH".cyan());
+        changed = watcher.wait()?;
+        println!(
+            "\n{} changed: {}",
+            "WATCH".cyan(),
+            changed
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+}
+
+fn formatters_for(format: OutputFormat, verbosity: Verbosity) -> Vec<Box<dyn Formatter>> {
+    match format {
+        OutputFormat::Text => vec![Box::new(TextFormatter::new(verbosity))],
+        OutputFormat::Tap => vec![Box::new(TapFormatter)],
+        OutputFormat::Github => vec![
+            Box::new(TextFormatter::new(verbosity)),
+            Box::new(GithubFormatter::default()),
+        ],
+    }
+}
+
+/// The default colored output, printing each result as soon as it is known
+struct TextFormatter {
+    verbosity: Verbosity,
+    planned: usize,
+    /// Whether a "running ..." line is on screen and must be cleared first
+    progress_shown: bool,
+}
+
+impl TextFormatter {
+    fn new(verbosity: Verbosity) -> Self {
+        TextFormatter {
+            verbosity,
+            planned: 0,
+            progress_shown: false,
+        }
+    }
+
+    fn clear_progress(&mut self) {
+        if self.progress_shown {
+            print!("\r\x1b[2K");
+            self.progress_shown = false;
+        }
+    }
+}
+
+impl Formatter for TextFormatter {
+    fn start(&mut self, run: &RunInfo) {
+        self.planned = run.planned;
+        if self.verbosity == Verbosity::Quiet {
+            return;
+        }
+
+        println!(
+            "Running tests from configuration: {}",
+            run.config_path.display()
+        );
+        if !run.filter.is_empty() {
+            println!(
+                "Filter: {} ({} test(s) selected)",
+                run.filter.describe(),
+                run.selected
+            );
+        }
+
+        if run.focused > 0 {
+            println!(
+                "{} {} test(s) marked `only`; the others are not run",
+                "NOTE".yellow(),
+                run.focused
+            );
+        }
+        println!();
+    }
+
+    fn event(&mut self, event: TestEvent) {
+        match event {
+            TestEvent::Started { index, test } => {
+                // 端末のときだけ実行中のテスト名を表示し、結果が出たら消す
+                if self.verbosity != Verbosity::Quiet && std::io::stdout().is_terminal() {
+                    print!(
+                        "{}",
+                        format!(
+                            "[{}/{}] running {} ...",
+                            index + 1,
+                            self.planned.max(index + 1),
+                            test.name
+                        )
+                        .dark_grey()
+                    );
+                    let _ = std::io::stdout().flush();
+                    self.progress_shown = true;
+                }
+            }
+            TestEvent::Finished { index, result } => {
+                self.clear_progress();
+                if self.verbosity == Verbosity::Quiet && !result.status.is_failure() {
+                    return;
+                }
+                print_result(index, self.planned.max(index + 1), result, self.verbosity);
+            }
+            // 失敗したビルドはエラーとして表示されるので、-v のときだけ各ステップを表示する
+            TestEvent::BuildFinished { step } => {
+                if self.verbosity == Verbosity::Verbose {
+                    self.clear_progress();
+                    print_build_step(step);
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self, outcome: &RunOutcome) {
+        self.clear_progress();
+        print_summary(outcome);
+    }
+}
+
+/// Colored status label as printed in the CLI output
+fn status_label(status: TestStatus) -> crossterm::style::StyledContent<&'static str> {
+    let label = status.label();
+    match status {
+        TestStatus::Passed => label.green(),
+        TestStatus::Failed => label.red(),
+        TestStatus::Skipped => label.dark_grey(),
+        TestStatus::XFailed => label.yellow(),
+        TestStatus::XPassed => label.magenta(),
+        TestStatus::Flaky => label.cyan(),
+        TestStatus::NotRun => label.dark_grey(),
+        TestStatus::Error => label.red().bold(),
+        TestStatus::HookFailed => label.dark_red().bold(),
+    }
+}
+
+/// Print one result line, with details for the tests that need attention
+fn print_result(index: usize, planned: usize, result: &TestResult, verbosity: Verbosity) {
+    let reason = result
+        .reason
+        .as_ref()
+        .map(|r| format!(" - {}", r))
+        .unwrap_or_default();
 
     println!(
-        "Running tests from configuration: {}",
-        config_path.display()
+        "[{}] Test #{}/{}: {} ({}ms){}",
+        status_label(result.status),
+        index + 1,
+        planned,
+        result.name,
+        result.execution_time.as_millis(),
+        reason
     );
+    if let Some(dir) = &result.temp_dir {
+        println!("  Temp dir kept: {}", dir.display());
+    }
 
-    // Run all tests
-    let test_results = test::run_tests(&config)?;
+    match result.status {
+        TestStatus::Failed => {
+            if result.attempts.len() > 1 {
+                print_attempts(result);
+            }
+            println!("  Command: {} {}", result.command, result.args.join(" "));
 
-    // Print results in a compact format
-    print_compact_results(&test_results);
+            if let Some(diff) = &result.diff {
+                println!("  Expected vs Actual:");
+                print_changes(diff);
+            }
+            // 期待どおりでなかったファイルごとに理由か差分を表示する
+            for check in result.failed_checks() {
+                println!("  {}", check.summary());
+                print_changes(check.diff.unwrap_or_default());
+            }
+            if result.dialog.iter().any(|event| !event.success) {
+                println!("  Dialog:");
+                print_dialog(&result.dialog, "    ");
+            }
+            if verbosity == Verbosity::Verbose {
+                print_indented("stderr", &result.stderr);
+            }
+            println!();
+        }
+        TestStatus::XPassed => {
+            println!("  Command: {} {}", result.command, result.args.join(" "));
+            println!("  Marked as xfail but the output matched; remove the marker.");
+            println!();
+        }
+        TestStatus::Flaky => {
+            print_attempts(result);
+            if verbosity == Verbosity::Verbose {
+                print_indented("stdout", &result.actual_output);
+                print_indented("stderr", &result.stderr);
+            }
+            println!();
+        }
+        // -v では成功したテストの出力も表示する
+        _ if verbosity == Verbosity::Verbose && !result.attempts.is_empty() => {
+            println!("  Command: {} {}", result.command, result.args.join(" "));
+            print_indented("stdout", &result.actual_output);
+            print_indented("stderr", &result.stderr);
+            println!();
+        }
+        _ => {}
+    }
+}
 
-    // Return success only if all tests passed
-    if test_results.iter().all(|r| r.success) {
-        Ok(())
-    } else {
-        Err(anyhow::anyhow!("Some tests failed"))
+fn print_build_step(step: &BuildStep) {
+    let warnings = step.diagnostics.len() - step.errors().count();
+    println!(
+        "[{}] {}{}{} ({}){}",
+        match (step.kind, step.success) {
+            (StepKind::Build, true) => "BUILD".green(),
+            (StepKind::Build, false) => "BUILD".red(),
+            (StepKind::Hook, true) => "HOOK".green(),
+            (StepKind::Hook, false) => "HOOK".red(),
+        },
+        step.name
+            .as_ref()
+            .map(|name| format!("{}: ", name))
+            .unwrap_or_default(),
+        step.command,
+        step.test
+            .as_ref()
+            .map(|test| format!(" for {}", test))
+            .unwrap_or_default(),
+        if step.cached {
+            "cached".to_string()
+        } else {
+            format!("{}ms", step.duration.as_millis())
+        },
+        if warnings > 0 {
+            format!(" - {} warning(s)", warnings).yellow().to_string()
+        } else {
+            String::new()
+        }
+    );
+    print_indented("stdout", &step.stdout);
+    print_indented("stderr", &step.stderr);
+}
+
+fn print_indented(title: &str, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    println!("  {}:", title);
+    for line in text.trim_end_matches('\n').lines() {
+        println!("    {}", line);
     }
 }
 
-/// Print test results in a compact format
-fn print_compact_results(results: &[TestResult]) {
-    let total = results.len();
-    let passed = results.iter().filter(|r| r.success).count();
-    let pass_rate = if total > 0 {
-        (passed as f64 / total as f64) * 100.0
-    } else {
-        0.0
-    };
+/// Print the totals, and the failing tests again so they don't get lost in the scrollback
+fn print_summary(outcome: &RunOutcome) {
+    let results = &outcome.results;
+    let stats = TestStats::from_results(results);
 
     println!("\n=== Test Results ===");
-    println!("Passed: {}/{} ({:.1}%)", passed, total, pass_rate);
-    println!("====================\n");
+    println!(
+        "Passed: {}/{} ({:.1}%)",
+        stats.ok(),
+        stats.executed(),
+        stats.pass_rate()
+    );
+    println!("{}", stats.summary());
+    println!("====================");
+
+    let failures: Vec<&TestResult> = results.iter().filter(|r| r.status.is_failure()).collect();
+    if !failures.is_empty() {
+        println!("\nFailed tests:");
+        for result in failures {
+            println!("  [{}] {}", status_label(result.status), result.name);
+        }
+    }
+    if let Some(reason) = &outcome.teardown_error {
+        println!("\n[{}] {}", "HOOK".dark_red().bold(), reason);
+    }
+}
 
-    // Print a compact summary of each test
-    for (i, result) in results.iter().enumerate() {
-        let status = if result.success {
-            "PASS".green()
+/// Parse a duration such as `90`, `90s`, `500ms`, `5m` or `1h` (plain numbers are seconds)
+pub fn parse_duration(value: &str) -> std::result::Result<std::time::Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration: {:?}", value))?;
+    let seconds = match unit.trim() {
+        "" | "s" => number,
+        "ms" => number / 1000.0,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        other => {
+            return Err(format!(
+                "unknown duration unit {:?} (use ms, s, m or h)",
+                other
+            ))
+        }
+    };
+
+    // 大きすぎる値や負の値はパニックさせずに引数の誤りにする
+    std::time::Duration::try_from_secs_f64(seconds)
+        .map_err(|e| format!("invalid duration {:?}: {}", value, e))
+}
+
+/// Print one line per attempt of a retried test
+fn print_attempts(result: &TestResult) {
+    println!("  Attempts:");
+    for (i, attempt) in result.attempts.iter().enumerate() {
+        println!(
+            "    #{} {} ({}ms){}",
+            i + 1,
+            if attempt.success {
+                "pass".green()
+            } else {
+                "fail".red()
+            },
+            attempt.execution_time.as_millis(),
+            if attempt.timed_out { " timed out" } else { "" }
+        );
+    }
+}
+
+/// Print the tests of a configuration with their arguments resolved
+pub fn list_tests(config_path: PathBuf, filter: &TestFilter) -> Result<()> {
+    let mut config = test::load_config(&config_path)
+        .with_context(|| format!("failed to load config from `{}`", config_path.display()))
+        .context(ErrorKind::ConfigInvalid)?;
+    filter.apply(&mut config);
+
+    println!("Tests in {}:", config_path.display());
+    for (i, test_case) in config.tests.iter().enumerate() {
+        let is_release = test::is_release_for(&config, test_case);
+        let args = test::resolve_args(test_case, is_release);
+
+        let tags = test_case
+            .tags
+            .as_ref()
+            .filter(|tags| !tags.is_empty())
+            .map(|tags| format!(" #{}", tags.join(" #")))
+            .unwrap_or_default();
+
+        let mut markers = Vec::new();
+        if test_case.is_skipped() {
+            markers.push("skip");
+        }
+        if test_case.is_only() {
+            markers.push("only");
+        }
+        if test_case.is_xfail() {
+            markers.push("xfail");
+        }
+        let markers = if markers.is_empty() {
+            String::new()
         } else {
-            "FAIL".red()
+            format!(" ({})", markers.join(", "))
         };
 
         println!(
-            "[{}] Test #{}: {} ({}ms)",
-            status,
+            "  {:>3}. {} [{}]{}{}",
             i + 1,
-            result.name,
-            result.execution_time.as_millis()
+            test_case.name.clone().bold(),
+            if is_release { "release" } else { "debug" },
+            markers.yellow(),
+            tags.cyan()
         );
+        println!("       $ {} {}", test_case.command_label(), args.join(" "));
+    }
+    println!("\n{} test(s)", config.tests.len());
 
-        // Only show details for failed tests
-        if !result.success {
-            println!("  Command: {} {}", result.command, result.args.join(" "));
-            println!("  Expected vs Actual:");
+    Ok(())
+}
 
-            if let Some(diff) = &result.diff {
-                for line in diff {
-                    match line.tag {
-                        similar::ChangeTag::Delete => println!("  - {}", line.content),
-                        similar::ChangeTag::Insert => println!("  + {}", line.content),
-                        similar::ChangeTag::Equal => {} // Skip equal lines for brevity
-                    }
-                }
+/// Check that a configuration file parses and is semantically valid
+pub fn validate(config_path: PathBuf) -> Result<()> {
+    let config = test::load_config(&config_path)
+        .with_context(|| format!("failed to load config from `{}`", config_path.display()))
+        .context(ErrorKind::ConfigInvalid)?;
+
+    let problems = test::validate_config(&config);
+    if problems.is_empty() {
+        println!(
+            "{} {} ({} test(s))",
+            "OK".green(),
+            config_path.display(),
+            config.tests.len()
+        );
+        Ok(())
+    } else {
+        for problem in &problems {
+            println!("{} {}", "ERROR".red(), problem);
+        }
+        Err(anyhow::anyhow!(
+            "{} problem(s) found in {}",
+            problems.len(),
+            config_path.display()
+        )
+        .context(ErrorKind::ConfigInvalid))
+    }
+}
+
+/// Write a starter configuration file in the format implied by its extension
+///
+/// Inside a Cargo project the configuration is generated from the binary targets of the
+/// workspace (see `scaffold`); elsewhere a generic example is written.
+pub fn init_config(path: &Path, force: bool, manifest_path: Option<&Path>) -> Result<()> {
+    if path.exists() && !force {
+        return Err(anyhow::anyhow!(
+            "{} already exists. Use --force to overwrite it.",
+            path.display()
+        ));
+    }
+
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+
+    let template = match extension.as_str() {
+        "yaml" | "yml" => INIT_TEMPLATE_YAML,
+        "toml" => INIT_TEMPLATE_TOML,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Unsupported configuration format: {}. Please use .yaml, .yml, or .toml files.",
+                extension
+            ))
+        }
+    };
+
+    let in_cargo_project = manifest_path.is_some() || Path::new("Cargo.toml").exists();
+    let scaffold = if in_cargo_project {
+        let metadata = cargo::metadata(manifest_path)?;
+        let cwd = std::env::current_dir()?;
+        Some(scaffold::from_metadata(&metadata, &cwd, manifest_path))
+            .filter(|scaffold| scaffold.binaries > 0)
+    } else {
+        None
+    };
+    if in_cargo_project && scaffold.is_none() {
+        eprintln!("No binary targets in the Cargo workspace; writing a generic example instead.");
+    }
+    let content = match &scaffold {
+        Some(scaffold) => scaffold::render(path, scaffold)?,
+        None => template.to_string(),
+    };
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    std::fs::write(path, content)
+        .with_context(|| format!("Failed to write config file: {}", path.display()))?;
+
+    println!("Created {}", path.display());
+    if let Some(scaffold) = &scaffold {
+        println!(
+            "  {} binary target(s), {} test(s)",
+            scaffold.binaries,
+            scaffold.tests.len()
+        );
+        if !scaffold.unmatched.is_empty() {
+            println!("  Skipped input/output files without a matching binary or counterpart:");
+            for file in &scaffold.unmatched {
+                println!("    {}", file.display());
             }
-            println!();
         }
     }
+    Ok(())
+}
+
+/// Run a single test by name and print everything about it
+pub fn show_test(config_path: PathBuf, name: &str) -> Result<()> {
+    let mut config = test::load_config(&config_path)
+        .with_context(|| format!("failed to load config from `{}`", config_path.display()))
+        .context(ErrorKind::ConfigInvalid)?;
+
+    config.tests.retain(|t| t.name == name);
+    if config.tests.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No test named {:?} in {}. Use `yamori list` to see the available tests.",
+            name,
+            config_path.display()
+        )
+        .context(ErrorKind::ConfigInvalid));
+    }
+
+    // show は明示的に指定されたテストを実行するので skip や only は無視する
+    for test_case in &mut config.tests {
+        test_case.skip = None;
+        test_case.only = None;
+    }
+
+    let outcome = test::run_tests(&config, &RunOptions::default())?;
+    for result in &outcome.results {
+        print_verbose_result(result);
+    }
+    if let Some(reason) = &outcome.teardown_error {
+        println!("[{}] {}", "HOOK".dark_red().bold(), reason);
+    }
+
+    if outcome.has_failures() {
+        Err(anyhow::anyhow!("Test failed: {}", name).context(ErrorKind::TestsFailed))
+    } else {
+        Ok(())
+    }
+}
+
+/// What `yamori record` runs and how the recorded test is named
+#[derive(Debug, Clone, Default)]
+pub struct RecordOptions {
+    pub name: Option<String>,
+    /// Feed this file to the command and reference it as `input_file`
+    pub input_file: Option<PathBuf>,
+    /// Read the input from stdin (typed interactively on a terminal)
+    pub stdin: bool,
+    pub timeout_secs: Option<u64>,
+}
+
+/// Run a command and append it as a new test expecting its current output
+pub fn record_test(
+    config_path: PathBuf,
+    command: &[String],
+    options: &RecordOptions,
+) -> Result<()> {
+    let (program, args) = command.split_first().ok_or_else(|| {
+        anyhow::anyhow!("No command given. Usage: yamori record -- <COMMAND> [ARGS]...")
+    })?;
+
+    // 既存の設定ファイルが壊れていたら書き足さない
+    let existing = if config_path.exists() {
+        Some(
+            test::load_config(&config_path)
+                .with_context(|| format!("failed to load config from `{}`", config_path.display()))
+                .context(ErrorKind::ConfigInvalid)?,
+        )
+    } else {
+        None
+    };
+
+    let name = options.name.clone().unwrap_or_else(|| command.join(" "));
+    if existing
+        .iter()
+        .flat_map(|config| &config.tests)
+        .any(|t| t.name == name)
+    {
+        return Err(anyhow::anyhow!(
+            "A test named {:?} already exists in {}. Use --name to choose another name.",
+            name,
+            config_path.display()
+        ));
+    }
+
+    let input = if let Some(path) = &options.input_file {
+        Some(
+            std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read input file: {}", path.display()))?,
+        )
+    } else if options.stdin {
+        if std::io::stdin().is_terminal() {
+            eprintln!("Type the input for the command, then press Ctrl-D:");
+        }
+        let mut input = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut input)
+            .context("Failed to read the input from stdin")?;
+        Some(input)
+    } else {
+        None
+    };
+
+    let mut test_case = TestCase {
+        name,
+        command: program.clone(),
+        cargo_bin: None,
+        args: (!args.is_empty()).then(|| args.to_vec()),
+        input,
+        input_file: None,
+        expected_output: None,
+        expected_output_file: None,
+        timeout_secs: options.timeout_secs,
+        build: None,
+        tags: None,
+        skip: None,
+        only: None,
+        xfail: None,
+        retries: None,
+        needs: None,
+        setup: None,
+        teardown: None,
+        isolate: None,
+        fixtures: None,
+        expected_files: None,
+        pty: None,
+        screen: None,
+        dialog: None,
+    };
+
+    // 設定のビルドコマンドは実行せず、コマンドだけを 1 回実行する
+    let config = TestConfig {
+        tests: vec![test_case.clone()],
+        build: None,
+        retries: None,
+        watch: None,
+        setup: None,
+        teardown: None,
+    };
+    let result = test::run_tests(&config, &RunOptions::default())?
+        .results
+        .pop()
+        .expect("one result per test");
+    match result.status {
+        TestStatus::Error => {
+            return Err(anyhow::anyhow!(
+                "Could not run {}: {}",
+                program,
+                result.reason.unwrap_or_default()
+            ))
+        }
+        _ if result.attempts.last().is_some_and(|a| a.timed_out) => {
+            return Err(anyhow::anyhow!(
+                "{} {}; nothing was recorded",
+                program,
+                result.reason.unwrap_or_default()
+            ))
+        }
+        _ => {}
+    }
+
+    println!("--- recorded output ---");
+    println!("{}", result.actual_output.trim_end());
+    if !result.stderr.is_empty() {
+        println!("\n--- stderr (not recorded) ---");
+        println!("{}", result.stderr.trim_end());
+    }
+
+    // 比較は前後の空白を無視するので、設定には末尾の改行を除いて書く
+    test_case.expected_output = Some(
+        result
+            .actual_output
+            .trim_end_matches(['\n', '\r'])
+            .to_string(),
+    );
+    if options.input_file.is_some() {
+        test_case.input = None;
+        test_case.input_file = options.input_file.clone();
+    }
+    record::append_test(&config_path, &test_case)?;
+
+    println!(
+        "\nAdded test {} to {}",
+        test_case.name.clone().bold(),
+        config_path.display()
+    );
+    Ok(())
+}
+
+/// Print every detail of a single test result
+fn print_verbose_result(result: &TestResult) {
+    println!(
+        "[{}] {}",
+        status_label(result.status),
+        result.name.clone().bold()
+    );
+    if let Some(reason) = &result.reason {
+        println!("  Reason:     {}", reason);
+    }
+    println!("  Command:    {} {}", result.command, result.args.join(" "));
+    println!(
+        "  Build mode: {}",
+        if result.is_release {
+            "release"
+        } else {
+            "debug"
+        }
+    );
+    println!("  Time:       {}ms", result.execution_time.as_millis());
+    if let Some(dir) = &result.temp_dir {
+        println!("  Temp dir:   {}", dir.display());
+    }
+    if let Some(commands) = &result.build_commands {
+        println!("  Build commands:");
+        for command in commands {
+            println!("    {}", command);
+        }
+    }
+
+    if let Some(input) = &result.input {
+        println!("\n--- input ---");
+        println!("{}", input.trim_end());
+    }
+
+    // 再試行があった場合はすべての試行の出力を表示する
+    if result.attempts.len() > 1 {
+        for (i, attempt) in result.attempts.iter().enumerate() {
+            println!(
+                "\n=== attempt {}/{}: {} ({}ms){} ===",
+                i + 1,
+                result.attempts.len(),
+                if attempt.success { "pass" } else { "fail" },
+                attempt.execution_time.as_millis(),
+                if attempt.timed_out { ", timed out" } else { "" }
+            );
+            print_output_and_diff(&result.with_attempt(i));
+        }
+    } else {
+        print_output_and_diff(result);
+    }
+    println!();
+}
+
+fn print_output_and_diff(result: &TestResult) {
+    println!("\n--- actual output ---");
+    println!("{}", result.actual_output.trim_end());
+
+    if !result.stderr.is_empty() {
+        println!("\n--- stderr ---");
+        println!("{}", result.stderr.trim_end());
+    }
+
+    if result.diff.is_some() {
+        println!("\n--- diff (- expected / + actual) ---");
+        print_diff(result);
+    }
+
+    for check in &result.file_checks {
+        println!(
+            "\n--- file {} ({}) ---",
+            check.path.display(),
+            if check.success { "ok" } else { "failed" }
+        );
+        if let Some(message) = &check.message {
+            println!("{}", message);
+        }
+        print_diff_lines(check.diff.as_deref().unwrap_or_default());
+    }
+    for check in &result.snapshot_checks {
+        println!(
+            "\n--- snapshot {} ({}) ---",
+            check.name,
+            if check.success { "ok" } else { "failed" }
+        );
+        if let Some(message) = &check.message {
+            println!("{}", message);
+        }
+        print_diff_lines(check.diff.as_deref().unwrap_or_default());
+    }
+    if !result.dialog.is_empty() {
+        println!("\n--- dialog ---");
+        print_dialog(&result.dialog, "");
+    }
+}
+
+// 対話の各ステップと、待っている間に読んだ出力を表示する
+fn print_dialog(events: &[DialogEvent], indent: &str) {
+    for event in events {
+        if let Some(expect) = &event.expect {
+            let status = if event.success { "ok" } else { "failed" };
+            println!("{}expect /{}/ ({})", indent, expect, status);
+            for line in event.output.lines() {
+                println!("{}  | {}", indent, line);
+            }
+        }
+        if let Some(message) = &event.message {
+            println!("{}{}", indent, message.as_str().red());
+        }
+        if let Some(send) = event.send.as_ref().filter(|_| event.success) {
+            println!("{}send {:?}", indent, send);
+        }
+    }
+}
+
+// 変更された行だけを表示する
+fn print_changes(diff: &[DiffLine]) {
+    for line in diff {
+        let content = line.content.trim_end_matches('\n');
+        match line.tag {
+            similar::ChangeTag::Delete => println!("  - {}", content),
+            similar::ChangeTag::Insert => println!("  + {}", content),
+            similar::ChangeTag::Equal => {} // Skip equal lines for brevity
+        }
+    }
+}
+
+fn print_diff(result: &TestResult) {
+    print_diff_lines(result.diff.as_deref().unwrap_or_default());
+}
+
+fn print_diff_lines(diff: &[DiffLine]) {
+    for line in diff {
+        println!("{}", diff_line(line));
+    }
+}
+
+fn diff_line(line: &DiffLine) -> String {
+    let content = line.content.trim_end_matches('\n');
+    match line.tag {
+        similar::ChangeTag::Delete => format!("- {}", content).red().to_string(),
+        similar::ChangeTag::Insert => format!("+ {}", content).green().to_string(),
+        similar::ChangeTag::Equal => format!("  {}", content),
+    }
+}
+
+const INIT_TEMPLATE_YAML: &str = r#"# yamori tests configuration
+
+# Global build configuration (used as fallback if test doesn't specify its own)
+build:
+  release: false
+  pre_build_commands:
+    - "cargo build {{#if release}}--release{{/if}}"
+
+tests:
+  - name: "Echo Test"
+    command: "echo"
+    args: ["Hello, World!"]
+    expected_output: "Hello, World!"
+    timeout_secs: 5
+
+  - name: "Word Count Test"
+    command: "wc"
+    args: ["-w"]
+    input: "one two three"
+    expected_output: "3"
+    timeout_secs: 5
+"#;
+
+const INIT_TEMPLATE_TOML: &str = r#"# yamori tests configuration
+
+[[tests]]
+name = "Echo Test"
+command = "echo"
+args = ["Hello, World!"]
+expected_output = "Hello, World!"
+timeout_secs = 5
+
+[[tests]]
+name = "Word Count Test"
+command = "wc"
+args = ["-w"]
+input = "one two three"
+expected_output = "3"
+timeout_secs = 5
+
+# Global build configuration (used as fallback if test doesn't specify its own)
+[build]
+release = false
+pre_build_commands = ["cargo build {{#if release}}--release{{/if}}"]
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::{init_config, parse_duration};
+    use crate::isolate::TempDir;
+    use crate::test;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration(" 2h "), Ok(Duration::from_secs(7200)));
+    }
+
+    #[test]
+    fn parse_duration_rejects_invalid_values() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("-5s").is_err());
+        assert!(parse_duration("10d").is_err());
+        assert!(parse_duration("1.2.3s").is_err());
+    }
+
+    #[test]
+    fn parse_duration_rejects_values_too_large_for_a_duration() {
+        let error = parse_duration("99999999999999999999999h").unwrap_err();
+        assert!(error.starts_with("invalid duration"), "{}", error);
+    }
+
+    // 入出力ファイルが 1 組ある Cargo プロジェクト
+    fn cargo_project(dir: &Path) {
+        std::fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nname = \"demo\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+        std::fs::create_dir_all(dir.join("tests")).unwrap();
+        std::fs::write(dir.join("tests/add.in"), "1 2\n").unwrap();
+        std::fs::write(dir.join("tests/add.out"), "3\n").unwrap();
+    }
+
+    #[test]
+    fn init_scaffolds_the_binaries_of_the_workspace() {
+        let dir = TempDir::create("init", &[]).unwrap();
+        cargo_project(dir.path());
+        let path = dir.path().join("yamori.yaml");
+
+        init_config(&path, false, Some(&dir.path().join("Cargo.toml"))).unwrap();
+
+        let config = test::load_config(&path).unwrap();
+        assert_eq!(config.tests.len(), 1);
+        let test_case = &config.tests[0];
+        assert_eq!(test_case.name, "demo - tests/add");
+        assert_eq!(
+            test_case.cargo_bin.as_ref().and_then(|c| c.bin.as_deref()),
+            Some("demo")
+        );
+        assert!(test_case
+            .input_file
+            .as_ref()
+            .is_some_and(|f| f.ends_with("tests/add.in")));
+        assert!(test_case
+            .expected_output_file
+            .as_ref()
+            .is_some_and(|f| f.ends_with("tests/add.out")));
+    }
+
+    #[test]
+    fn init_overwrites_an_existing_file_only_with_force() {
+        let dir = TempDir::create("init force", &[]).unwrap();
+        cargo_project(dir.path());
+        let manifest = dir.path().join("Cargo.toml");
+        let path = dir.path().join("yamori.toml");
+        std::fs::write(&path, "# keep me\n").unwrap();
+
+        let error = init_config(&path, false, Some(&manifest)).unwrap_err();
+        assert!(error.to_string().contains("--force"), "{}", error);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "# keep me\n");
+
+        init_config(&path, true, Some(&manifest)).unwrap();
+        let config = test::load_config(&path).unwrap();
+        assert_eq!(config.tests[0].name, "demo - tests/add");
+    }
 }
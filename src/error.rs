@@ -0,0 +1,48 @@
+// src/error.rs
+use std::fmt;
+
+/// Why a command failed, which decides the exit status of the process
+///
+/// Attached to an [`anyhow::Error`] as context; errors without a kind are internal errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// At least one test failed, errored or unexpectedly passed
+    TestsFailed,
+    /// The configuration file is missing, cannot be parsed or is invalid
+    ConfigInvalid,
+    /// A global pre-build command failed
+    BuildFailed,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::TestsFailed => 1,
+            ErrorKind::ConfigInvalid => 2,
+            ErrorKind::BuildFailed => 3,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorKind::TestsFailed => "Some tests failed",
+            ErrorKind::ConfigInvalid => "Invalid configuration",
+            ErrorKind::BuildFailed => "Build failed",
+        })
+    }
+}
+
+impl std::error::Error for ErrorKind {}
+
+/// Exit status for an error that is not attached to any [`ErrorKind`]
+pub const INTERNAL_ERROR_EXIT_CODE: i32 = 4;
+
+/// Exit status for the given error
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    // anyhow は context として付けた型にも downcast できる
+    error
+        .downcast_ref::<ErrorKind>()
+        .map_or(INTERNAL_ERROR_EXIT_CODE, |kind| kind.exit_code())
+}
@@ -0,0 +1,149 @@
+// src/filter.rs
+use crate::test::TestConfig;
+use regex::Regex;
+
+/// Selects a subset of the tests by name and tags
+#[derive(Debug, Clone, Default)]
+pub struct TestFilter {
+    /// Substring or regular expression matched against the test name
+    pub pattern: Option<String>,
+    /// Only run tests that carry at least one of these tags
+    pub tags: Vec<String>,
+    /// Never run tests that carry any of these tags
+    pub exclude_tags: Vec<String>,
+    regex: Option<Regex>,
+}
+
+impl TestFilter {
+    pub fn new(pattern: Option<String>, tags: Vec<String>, exclude_tags: Vec<String>) -> Self {
+        let mut filter = TestFilter {
+            pattern: None,
+            tags,
+            exclude_tags,
+            regex: None,
+        };
+        if let Some(pattern) = pattern {
+            filter.set_pattern(&pattern);
+        }
+        filter
+    }
+
+    /// Replace the name pattern; an empty string clears it
+    pub fn set_pattern(&mut self, pattern: &str) {
+        if pattern.is_empty() {
+            self.pattern = None;
+            self.regex = None;
+            return;
+        }
+
+        // 正規表現として解釈できなければ部分一致として扱う
+        self.regex = Some(Regex::new(pattern).unwrap_or_else(|_| {
+            Regex::new(&regex::escape(pattern)).expect("escaped pattern is valid")
+        }));
+        self.pattern = Some(pattern.to_string());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pattern.is_none() && self.tags.is_empty() && self.exclude_tags.is_empty()
+    }
+
+    pub fn matches(&self, name: &str, tags: &[String]) -> bool {
+        if let Some(regex) = &self.regex {
+            if !regex.is_match(name) {
+                return false;
+            }
+        }
+
+        if !self.tags.is_empty() && !tags.iter().any(|t| self.tags.contains(t)) {
+            return false;
+        }
+
+        !tags.iter().any(|t| self.exclude_tags.contains(t))
+    }
+
+    /// Drop every test of the configuration that does not match
+    pub fn apply(&self, config: &mut TestConfig) {
+        if self.is_empty() {
+            return;
+        }
+        config
+            .tests
+            .retain(|t| self.matches(&t.name, t.tags.as_deref().unwrap_or_default()));
+    }
+
+    /// Short description for status lines, e.g. `/echo/ +fast -slow`
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(pattern) = &self.pattern {
+            parts.push(format!("/{}/", pattern));
+        }
+        parts.extend(self.tags.iter().map(|t| format!("+{}", t)));
+        parts.extend(self.exclude_tags.iter().map(|t| format!("-{}", t)));
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn pattern_is_a_regex() {
+        let filter = TestFilter::new(Some("^echo (a|b)$".to_string()), vec![], vec![]);
+
+        assert!(filter.matches("echo a", &[]));
+        assert!(!filter.matches("echo c", &[]));
+        assert!(!filter.matches("big echo a", &[]));
+    }
+
+    #[test]
+    fn invalid_regex_is_matched_as_a_substring() {
+        let filter = TestFilter::new(Some("add (1".to_string()), vec![], vec![]);
+
+        assert!(filter.matches("add (1 + 2)", &[]));
+        assert!(!filter.matches("add 1", &[]));
+        assert_eq!(filter.pattern.as_deref(), Some("add (1"));
+    }
+
+    #[test]
+    fn empty_pattern_clears_the_filter() {
+        let mut filter = TestFilter::new(Some("echo".to_string()), vec![], vec![]);
+        filter.set_pattern("");
+
+        assert!(filter.is_empty());
+        assert!(filter.matches("anything", &[]));
+    }
+
+    #[test]
+    fn tags_select_and_exclude_tests() {
+        let filter = TestFilter::new(None, tags(&["fast", "unit"]), tags(&["flaky"]));
+
+        assert!(filter.matches("a", &tags(&["fast"])));
+        assert!(filter.matches("b", &tags(&["slow", "unit"])));
+        assert!(!filter.matches("c", &tags(&["slow"])));
+        assert!(!filter.matches("d", &[]));
+        // 除外するタグは選ぶタグより優先する
+        assert!(!filter.matches("e", &tags(&["fast", "flaky"])));
+
+        let exclude_only = TestFilter::new(None, vec![], tags(&["flaky"]));
+        assert!(exclude_only.matches("f", &[]));
+        assert!(!exclude_only.matches("g", &tags(&["flaky"])));
+    }
+
+    #[test]
+    fn describe_lists_the_pattern_and_tags() {
+        let filter = TestFilter::new(
+            Some("echo".to_string()),
+            tags(&["fast"]),
+            tags(&["slow", "flaky"]),
+        );
+        assert_eq!(filter.describe(), "/echo/ +fast -slow -flaky");
+
+        assert_eq!(TestFilter::default().describe(), "");
+        assert_eq!(TestFilter::new(None, tags(&["a"]), vec![]).describe(), "+a");
+    }
+}
@@ -0,0 +1,463 @@
+// src/graph.rs
+use crate::build::BuildStep;
+use crate::cache::BuildCache;
+use crate::test::NamedStep;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use std::thread::Scope;
+
+/// Progress of a named build step during a run
+#[derive(Debug, Clone, PartialEq)]
+enum StepState {
+    Pending,
+    Running,
+    Succeeded,
+    /// The step failed, or could not start; the message says why
+    Failed(String),
+}
+
+impl StepState {
+    fn is_done(&self) -> bool {
+        matches!(self, StepState::Succeeded | StepState::Failed(_))
+    }
+}
+
+/// The named build steps of a run, executed in parallel as their dependencies succeed
+///
+/// Worker threads run the steps; the test loop waits for the steps a test needs and receives
+/// the finished steps so that they can be reported on its thread.
+pub struct BuildGraph<'a> {
+    steps: &'a [NamedStep],
+    /// Commands with their template expanded
+    commands: Vec<String>,
+    /// Indices of the dependencies of each step
+    dependencies: Vec<Vec<usize>>,
+    state: Mutex<GraphState>,
+    changed: Condvar,
+}
+
+struct GraphState {
+    steps: Vec<StepState>,
+    /// Finished steps not yet taken by the test loop
+    finished: Vec<BuildStep>,
+    /// No new step is started once the run stopped
+    cancelled: bool,
+}
+
+impl<'a> BuildGraph<'a> {
+    /// Check the steps and their dependencies; `expand` resolves the command templates
+    pub fn new(steps: &'a [NamedStep], expand: impl Fn(&str) -> String) -> Result<Self> {
+        let problems = check(steps);
+        if !problems.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Invalid build steps:\n  {}",
+                problems.join("\n  ")
+            ));
+        }
+
+        let index: HashMap<&str, usize> = steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| (step.name.as_str(), i))
+            .collect();
+        let dependencies = steps
+            .iter()
+            .map(|step| {
+                step.depends_on
+                    .iter()
+                    .flatten()
+                    .map(|name| index[name.as_str()])
+                    .collect()
+            })
+            .collect();
+
+        Ok(BuildGraph {
+            steps,
+            commands: steps.iter().map(|step| expand(&step.command)).collect(),
+            dependencies,
+            state: Mutex::new(GraphState {
+                steps: vec![StepState::Pending; steps.len()],
+                finished: Vec::new(),
+                cancelled: false,
+            }),
+            changed: Condvar::new(),
+        })
+    }
+
+    /// Start `jobs` threads running the steps
+    pub fn start<'scope>(
+        &'scope self,
+        scope: &'scope Scope<'scope, '_>,
+        builds: &'scope BuildCache,
+        jobs: usize,
+    ) {
+        for _ in 0..jobs.clamp(1, self.steps.len().max(1)) {
+            scope.spawn(move || self.work(builds));
+        }
+    }
+
+    /// Stop starting new steps; the running ones are still waited for
+    pub fn cancel(&self) {
+        self.lock().cancelled = true;
+        self.changed.notify_all();
+    }
+
+    /// Wait until the named steps (every step if `names` is `None`) succeeded
+    ///
+    /// Finished steps are passed to `on_step` while waiting. Fails if one of the steps failed,
+    /// did not start because a dependency failed, or does not exist.
+    pub fn wait(
+        &self,
+        names: Option<&[String]>,
+        on_step: &mut dyn FnMut(&BuildStep),
+    ) -> Result<(), String> {
+        let wanted: Vec<usize> = match names {
+            Some(names) => names
+                .iter()
+                .map(|name| {
+                    self.steps
+                        .iter()
+                        .position(|step| &step.name == name)
+                        .ok_or_else(|| format!("unknown build step '{}'", name))
+                })
+                .collect::<Result<_, _>>()?,
+            None => (0..self.steps.len()).collect(),
+        };
+
+        let mut state = self.lock();
+        loop {
+            // 終わったステップの報告はロックを外してから行う
+            let finished = std::mem::take(&mut state.finished);
+            if !finished.is_empty() {
+                drop(state);
+                for step in &finished {
+                    on_step(step);
+                }
+                state = self.lock();
+                continue;
+            }
+
+            if let Some(message) = wanted.iter().find_map(|&i| match &state.steps[i] {
+                StepState::Failed(message) => Some(message.clone()),
+                _ => None,
+            }) {
+                return Err(message);
+            }
+            if wanted
+                .iter()
+                .all(|&i| state.steps[i] == StepState::Succeeded)
+            {
+                return Ok(());
+            }
+            // 打ち切り後は始まらないステップを待たない
+            if state.cancelled && wanted.iter().all(|&i| state.steps[i] != StepState::Running) {
+                return Err("the run was stopped before the build steps finished".to_string());
+            }
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Wait for the running steps and report every finished step
+    pub fn finish(&self, on_step: &mut dyn FnMut(&BuildStep)) {
+        self.cancel();
+        let mut state = self.lock();
+        while state.steps.contains(&StepState::Running) {
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        let finished = std::mem::take(&mut state.finished);
+        drop(state);
+        for step in &finished {
+            on_step(step);
+        }
+    }
+
+    // 依存するステップがすべて成功したステップを順に実行する
+    fn work(&self, builds: &BuildCache) {
+        let mut state = self.lock();
+        loop {
+            self.fail_blocked(&mut state);
+            if state.cancelled || state.steps.iter().all(StepState::is_done) {
+                return;
+            }
+            let ready = (0..self.steps.len()).find(|&i| {
+                state.steps[i] == StepState::Pending
+                    && self.dependencies[i]
+                        .iter()
+                        .all(|&d| state.steps[d] == StepState::Succeeded)
+            });
+            let Some(index) = ready else {
+                state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+                continue;
+            };
+
+            state.steps[index] = StepState::Running;
+            drop(state);
+            let outcome = self.run(builds, index);
+            state = self.lock();
+            match outcome {
+                Ok(step) => {
+                    state.steps[index] = if step.success {
+                        StepState::Succeeded
+                    } else {
+                        StepState::Failed(format!(
+                            "build step '{}' failed: {}\n\n{}",
+                            self.steps[index].name,
+                            self.commands[index],
+                            step.failure_output()
+                        ))
+                    };
+                    state.finished.push(step);
+                }
+                Err(e) => {
+                    state.steps[index] = StepState::Failed(format!(
+                        "build step '{}' could not run: {:#}",
+                        self.steps[index].name, e
+                    ));
+                }
+            }
+            self.changed.notify_all();
+        }
+    }
+
+    fn run(&self, builds: &BuildCache, index: usize) -> Result<BuildStep> {
+        let step = &self.steps[index];
+        let command = &self.commands[index];
+        let result = builds.run(
+            command,
+            step.inputs.as_deref().unwrap_or_default(),
+            step.outputs.as_deref().unwrap_or_default(),
+            None,
+        )?;
+        // 同じコマンドが先に成功していれば、その結果を使う
+        let mut result = result.unwrap_or_else(|| BuildStep::cached(command, None));
+        result.name = Some(step.name.clone());
+        Ok(result)
+    }
+
+    // 依存するステップが失敗したステップは実行せずに失敗とする
+    fn fail_blocked(&self, state: &mut GraphState) {
+        let mut any = false;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in 0..self.steps.len() {
+                if state.steps[i] != StepState::Pending {
+                    continue;
+                }
+                let failed = self.dependencies[i]
+                    .iter()
+                    .find(|&&d| matches!(state.steps[d], StepState::Failed(_)));
+                if let Some(&d) = failed {
+                    state.steps[i] = StepState::Failed(format!(
+                        "build step '{}' was not run because '{}' failed",
+                        self.steps[i].name, self.steps[d].name
+                    ));
+                    changed = true;
+                    any = true;
+                }
+            }
+        }
+        if any {
+            self.changed.notify_all();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, GraphState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Problems of the build steps: duplicate names, unknown dependencies and cycles
+pub fn check(steps: &[NamedStep]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut index = HashMap::new();
+    for (i, step) in steps.iter().enumerate() {
+        if step.name.trim().is_empty() {
+            problems.push(format!("build step #{}: name must not be empty", i + 1));
+        } else if index.insert(step.name.as_str(), i).is_some() {
+            problems.push(format!("build step '{}': duplicate name", step.name));
+        }
+    }
+    for step in steps {
+        for dependency in step.depends_on.iter().flatten() {
+            if !index.contains_key(dependency.as_str()) {
+                problems.push(format!(
+                    "build step '{}': depends on unknown step '{}'",
+                    step.name, dependency
+                ));
+            }
+        }
+    }
+    if !problems.is_empty() {
+        return problems;
+    }
+
+    // 深さ優先探索で循環を探す（0: 未訪問, 1: 探索中, 2: 完了）
+    fn visit(
+        i: usize,
+        steps: &[NamedStep],
+        index: &HashMap<&str, usize>,
+        marks: &mut [u8],
+        path: &mut Vec<usize>,
+    ) -> Option<Vec<usize>> {
+        match marks[i] {
+            2 => return None,
+            1 => {
+                let start = path.iter().position(|&p| p == i).unwrap_or(0);
+                let mut cycle = path[start..].to_vec();
+                cycle.push(i);
+                return Some(cycle);
+            }
+            _ => {}
+        }
+        marks[i] = 1;
+        path.push(i);
+        for dependency in steps[i].depends_on.iter().flatten() {
+            if let Some(cycle) = visit(index[dependency.as_str()], steps, index, marks, path) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        marks[i] = 2;
+        None
+    }
+
+    let mut marks = vec![0; steps.len()];
+    for i in 0..steps.len() {
+        if let Some(cycle) = visit(i, steps, &index, &mut marks, &mut Vec::new()) {
+            let names: Vec<&str> = cycle.iter().map(|&c| steps[c].name.as_str()).collect();
+            problems.push(format!(
+                "build steps depend on each other: {}",
+                names.join(" -> ")
+            ));
+            break;
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(name: &str, depends_on: &[&str]) -> NamedStep {
+        NamedStep {
+            name: name.to_string(),
+            command: format!("echo {}", name),
+            depends_on: Some(depends_on.iter().map(|d| d.to_string()).collect()),
+            inputs: None,
+            outputs: None,
+        }
+    }
+
+    fn graph(steps: &[NamedStep]) -> BuildGraph<'_> {
+        BuildGraph::new(steps, str::to_string).unwrap()
+    }
+
+    #[test]
+    fn check_accepts_a_valid_graph() {
+        let steps = [step("a", &[]), step("b", &["a"]), step("c", &["a", "b"])];
+        assert!(check(&steps).is_empty());
+    }
+
+    #[test]
+    fn check_reports_duplicate_names() {
+        let steps = [step("a", &[]), step("a", &[])];
+        assert_eq!(check(&steps), ["build step 'a': duplicate name"]);
+    }
+
+    #[test]
+    fn check_reports_unknown_dependencies() {
+        let steps = [step("a", &["missing"])];
+        assert_eq!(
+            check(&steps),
+            ["build step 'a': depends on unknown step 'missing'"]
+        );
+    }
+
+    #[test]
+    fn check_reports_cycles() {
+        let steps = [step("a", &["b"]), step("b", &["a"])];
+        assert_eq!(
+            check(&steps),
+            ["build steps depend on each other: a -> b -> a"]
+        );
+        assert!(BuildGraph::new(&steps, str::to_string).is_err());
+    }
+
+    #[test]
+    fn fail_blocked_fails_the_dependents_of_a_failed_step() {
+        let steps = [
+            step("a", &[]),
+            step("b", &["a"]),
+            step("c", &["b"]),
+            step("d", &[]),
+        ];
+        let graph = graph(&steps);
+        let mut state = graph.lock();
+        state.steps[0] = StepState::Failed("a failed".to_string());
+        graph.fail_blocked(&mut state);
+
+        assert_eq!(
+            state.steps[1],
+            StepState::Failed("build step 'b' was not run because 'a' failed".to_string())
+        );
+        assert_eq!(
+            state.steps[2],
+            StepState::Failed("build step 'c' was not run because 'b' failed".to_string())
+        );
+        assert_eq!(state.steps[3], StepState::Pending);
+    }
+
+    #[test]
+    fn wait_returns_the_failure_of_a_wanted_step() {
+        let steps = [step("a", &[]), step("b", &[])];
+        let graph = graph(&steps);
+        graph.lock().steps[0] = StepState::Failed("a failed".to_string());
+        graph.lock().steps[1] = StepState::Succeeded;
+
+        let wanted = ["b".to_string()];
+        assert_eq!(graph.wait(Some(&wanted), &mut |_| {}), Ok(()));
+        assert_eq!(graph.wait(None, &mut |_| {}), Err("a failed".to_string()));
+    }
+
+    #[test]
+    fn wait_rejects_unknown_steps() {
+        let steps = [step("a", &[])];
+        let graph = graph(&steps);
+        let wanted = ["missing".to_string()];
+        assert_eq!(
+            graph.wait(Some(&wanted), &mut |_| {}),
+            Err("unknown build step 'missing'".to_string())
+        );
+    }
+
+    #[test]
+    fn wait_fails_after_cancel() {
+        let steps = [step("a", &[])];
+        let graph = graph(&steps);
+        graph.cancel();
+        assert_eq!(
+            graph.wait(None, &mut |_| {}),
+            Err("the run was stopped before the build steps finished".to_string())
+        );
+    }
+
+    #[test]
+    fn wait_after_cancel_still_waits_for_running_steps() {
+        let steps = [step("a", &[])];
+        let graph = graph(&steps);
+        graph.lock().steps[0] = StepState::Running;
+        graph.cancel();
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                graph.lock().steps[0] = StepState::Succeeded;
+                graph.changed.notify_all();
+            });
+            assert_eq!(graph.wait(None, &mut |_| {}), Ok(()));
+        });
+    }
+}
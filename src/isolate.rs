@@ -0,0 +1,119 @@
+// src/isolate.rs
+use anyhow::{Context, Result};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// 同じプロセスで作るディレクトリの名前が重ならないようにする
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Fresh working directory of an isolated test, removed when dropped unless kept
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl TempDir {
+    /// Create a directory in the system temp directory and copy the fixtures into it
+    ///
+    /// Relative fixture paths keep their place under the new directory; absolute paths and
+    /// paths leaving the working directory are copied by their file name.
+    pub fn create(test: &str, fixtures: &[PathBuf]) -> Result<Self> {
+        let slug: String = test
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .take(40)
+            .collect();
+        let base = std::env::temp_dir();
+        let dir = loop {
+            let path = base.join(format!(
+                "yamori-{}-{}-{}",
+                slug,
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            match std::fs::create_dir(&path) {
+                Ok(()) => break TempDir { path, keep: false },
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("Failed to create temp directory: {}", path.display())
+                    })
+                }
+            }
+        };
+
+        for fixture in fixtures {
+            let target = dir.path.join(destination(fixture));
+            copy(fixture, &target).with_context(|| {
+                format!(
+                    "Failed to copy fixture of test '{}': {}",
+                    test,
+                    fixture.display()
+                )
+            })?;
+        }
+        Ok(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Leave the directory in place and return its path
+    pub fn keep(mut self) -> PathBuf {
+        self.keep = true;
+        self.path.clone()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if !self.keep {
+            // 消せなくてもテストの結果は変わらないので無視する
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+/// Make a relative program path absolute, since the test runs in another directory
+///
+/// Bare names such as `echo` are left alone so that they are still looked up in `PATH`.
+pub fn resolve_program(program: &str) -> String {
+    let path = Path::new(program);
+    if path.is_relative() && path.components().count() > 1 {
+        if let Ok(cwd) = std::env::current_dir() {
+            return cwd.join(path).display().to_string();
+        }
+    }
+    program.to_string()
+}
+
+// 一時ディレクトリの中でのフィクスチャの置き場所
+fn destination(fixture: &Path) -> PathBuf {
+    let inside = fixture
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if inside {
+        fixture.to_path_buf()
+    } else {
+        fixture.file_name().map(PathBuf::from).unwrap_or_default()
+    }
+}
+
+// ファイルまたはディレクトリを再帰的にコピーする
+fn copy(from: &Path, to: &Path) -> Result<()> {
+    if from.is_dir() {
+        std::fs::create_dir_all(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(from, to)?;
+    }
+    Ok(())
+}
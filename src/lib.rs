@@ -1,4 +1,18 @@
 pub mod app;
+pub mod bless;
+pub mod build;
+pub mod cache;
+pub mod cargo;
 pub mod cli;
+pub mod error;
+pub mod filter;
+pub mod graph;
+pub mod isolate;
+pub mod output;
+pub mod pty;
+pub mod record;
+pub mod report;
+pub mod scaffold;
 pub mod test;
 pub mod ui;
+pub mod watch;
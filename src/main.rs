@@ -1,74 +1,441 @@
 // src/main.rs
 mod app;
+mod bless;
+mod build;
+mod cache;
+mod cargo;
 mod cli;
+mod error;
+mod filter;
+mod graph;
+mod isolate;
+mod output;
+mod pty;
+mod record;
+mod report;
+mod scaffold;
 mod test;
 mod ui;
+mod watch;
 
 use anyhow::{Context, Result};
 use app::{App, PopupType};
 use chrono::TimeZone;
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use crossterm::{
     event::{self, Event, KeyCode},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use filter::TestFilter;
 use ratatui::{backend::CrosstermBackend, Terminal};
-use std::{env, io, path::PathBuf, time::Duration};
+use std::{
+    env, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// Path to the test configuration file (YAML or TOML)
     #[arg(
         short = 'y',
         long = "yamori-config",
-        default_value = "tests/configs/tests.toml"
+        default_value = "tests/configs/tests.toml",
+        global = true
     )]
     config: std::path::PathBuf,
 
-    /// Run in CLI mode (no TUI)
+    /// Run in CLI mode (no TUI). Same as `yamori run --headless`
     #[arg(short = 'c', long = "cli", default_value = "false")]
     cli_mode: bool,
+
+    // サブコマンドなしで実行したときは run と同じオプションを受け付ける
+    #[command(flatten)]
+    run: RunCommand,
+}
+
+/// Options of `run`, also accepted without a subcommand
+#[derive(clap::Args, Debug)]
+struct RunCommand {
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    #[command(flatten)]
+    run: RunArgs,
+
+    #[command(flatten)]
+    watch: WatchArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct FilterArgs {
+    /// Only run tests whose name matches this substring or regular expression
+    #[arg(long, value_name = "PATTERN")]
+    filter: Option<String>,
+
+    /// Only run tests with this tag (repeatable; a test needs any one of them)
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+
+    /// Skip tests with this tag (repeatable)
+    #[arg(long = "exclude-tag", value_name = "TAG")]
+    exclude_tags: Vec<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct RunArgs {
+    /// Stop at the first failing test (the remaining tests are reported as not run)
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// Stop after this many failing tests
+    #[arg(long, value_name = "N", conflicts_with = "fail_fast")]
+    max_failures: Option<usize>,
+
+    /// Time budget for the whole run, e.g. `90s`, `5m` or `1h`
+    #[arg(long, value_name = "DURATION", value_parser = cli::parse_duration)]
+    max_duration: Option<Duration>,
+
+    /// Keep the temporary directories of failing isolated tests for debugging
+    #[arg(long)]
+    keep_temp: bool,
+
+    /// How a headless run prints its results
+    #[arg(long, value_enum, default_value_t = output::OutputFormat::Text)]
+    format: output::OutputFormat,
+
+    /// Only print the failing tests and the totals
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Also print the command, stdout and stderr of the passing tests
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Also write the results as a report, e.g. `junit=target/yamori.xml`; formats: junit, json, jsonl, html (repeatable)
+    #[arg(long = "report", value_name = "FORMAT=PATH")]
+    reports: Vec<report::ReportSpec>,
+
+    /// Write the actual output of failing tests back as their expected output (asks first)
+    #[arg(long)]
+    bless: bool,
+
+    /// Bless without asking for confirmation
+    #[arg(long, requires = "bless")]
+    yes: bool,
+
+    /// Exit with status 0 even if tests failed (the reports are still written)
+    #[arg(long)]
+    no_fail: bool,
+
+    /// Write a self-contained HTML report (same as `--report html=PATH`)
+    #[arg(long, value_name = "PATH")]
+    html: Option<PathBuf>,
+}
+
+impl FilterArgs {
+    fn into_filter(self) -> TestFilter {
+        TestFilter::new(self.filter, self.tags, self.exclude_tags)
+    }
+}
+
+impl RunArgs {
+    fn options(&self) -> test::RunOptions {
+        test::RunOptions {
+            max_failures: if self.fail_fast {
+                Some(1)
+            } else {
+                self.max_failures
+            },
+            max_duration: self.max_duration,
+            keep_temp: self.keep_temp,
+        }
+    }
+
+    fn output(&self) -> cli::OutputOptions {
+        cli::OutputOptions {
+            format: self.format,
+            bless: self.bless,
+            assume_yes: self.yes,
+            no_fail: self.no_fail,
+            verbosity: if self.quiet {
+                output::Verbosity::Quiet
+            } else if self.verbose {
+                output::Verbosity::Verbose
+            } else {
+                output::Verbosity::Normal
+            },
+            reports: self
+                .reports
+                .iter()
+                .cloned()
+                .chain(self.html.iter().map(|path| report::ReportSpec {
+                    format: report::ReportFormat::Html,
+                    path: path.clone(),
+                }))
+                .collect(),
+        }
+    }
 }
 
-fn main() -> Result<()> {
+#[derive(clap::Args, Debug)]
+struct WatchArgs {
+    /// Rerun the tests whenever the config, fixture or watched source files change
+    #[arg(long, conflicts_with = "bless")]
+    watch: bool,
+
+    /// Also watch files matching this glob, e.g. `src/**/*.rs` (repeatable)
+    #[arg(long = "watch-glob", value_name = "GLOB")]
+    watch_globs: Vec<String>,
+
+    /// In watch mode, run the affected and previously failed tests alone first
+    #[arg(long)]
+    affected_first: bool,
+}
+
+impl WatchArgs {
+    fn options(&self) -> Option<watch::WatchOptions> {
+        self.watch.then(|| watch::WatchOptions {
+            globs: self.watch_globs.clone(),
+            affected_first: self.affected_first,
+        })
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Run the tests in the TUI, or headless with --headless
+    Run {
+        /// Run without the TUI and print the results to stdout
+        #[arg(long, visible_alias = "cli")]
+        headless: bool,
+
+        #[command(flatten)]
+        options: RunCommand,
+    },
+    /// List the tests defined in the configuration
+    List {
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Check the configuration file without running any test
+    Validate,
+    /// Create a starter configuration file
+    Init {
+        /// Where to write the configuration (defaults to --yamori-config)
+        path: Option<PathBuf>,
+
+        /// Overwrite the file if it already exists
+        #[arg(long)]
+        force: bool,
+
+        /// Cargo.toml of the workspace to generate the tests from (defaults to ./Cargo.toml)
+        #[arg(long, value_name = "PATH")]
+        manifest_path: Option<PathBuf>,
+    },
+    /// Run a single test and show its full output
+    Show {
+        /// Name of the test to run
+        name: String,
+    },
+    /// Run a command and add it as a new test expecting its current output
+    Record {
+        /// Name of the new test (defaults to the command line)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Feed this file to the command; the test refers to it as `input_file`
+        #[arg(long, value_name = "PATH", conflicts_with = "stdin")]
+        input_file: Option<PathBuf>,
+
+        /// Read the input from stdin; on a terminal, type it and end with Ctrl-D
+        #[arg(long)]
+        stdin: bool,
+
+        /// Timeout of the new test in seconds
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<u64>,
+
+        /// The command to record and its arguments, after `--`
+        #[arg(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+}
+
+fn main() {
+    // 終了コードはエラーの種類で決まる（README の Exit Codes を参照）
+    if let Err(e) = run() {
+        eprintln!("Error: {:?}", e);
+        std::process::exit(error::exit_code(&e));
+    }
+}
+
+fn run() -> Result<()> {
     // 環境変数 YAMORI_CONFIG から設定ファイルのパスを取得
     let config_from_env = env::var("YAMORI_CONFIG").ok();
 
     // コマンドライン引数を解析
-    let mut args = Args::parse();
+    let matches = Args::command().get_matches();
+    reject_run_options(&matches);
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
     // 環境変数から設定ファイルのパスが指定されていれば、それを優先
     if let Some(config_path) = config_from_env {
         args.config = PathBuf::from(config_path);
     }
 
-    // Check if CLI mode is enabled
-    if args.cli_mode {
-        return cli::run_cli(args.config);
+    // サブコマンドが指定されていない場合は従来のフラグに従って run と同じ動作をする
+    let command = args.command.unwrap_or(Commands::Run {
+        headless: args.cli_mode,
+        options: args.run,
+    });
+
+    match command {
+        Commands::Run { headless, options } => {
+            let filter = options.filter.into_filter();
+            let run_options = options.run.options();
+            let output = options.run.output();
+            match (headless, options.watch.options()) {
+                (true, Some(watch_options)) => {
+                    cli::watch_cli(args.config, &filter, &run_options, &output, &watch_options)
+                }
+                (true, None) => cli::run_cli(args.config, &filter, &run_options, &output),
+                (false, watch_options) => run_tui(
+                    args.config,
+                    filter,
+                    run_options,
+                    &output.reports,
+                    watch_options,
+                ),
+            }
+        }
+        Commands::List { filter } => cli::list_tests(args.config, &filter.into_filter()),
+        Commands::Validate => cli::validate(args.config),
+        Commands::Init {
+            path,
+            force,
+            manifest_path,
+        } => cli::init_config(
+            path.as_deref().unwrap_or(&args.config),
+            force,
+            manifest_path.as_deref(),
+        ),
+        Commands::Show { name } => cli::show_test(args.config, &name),
+        Commands::Record {
+            name,
+            input_file,
+            stdin,
+            timeout,
+            command,
+        } => cli::record_test(
+            args.config,
+            &command,
+            &cli::RecordOptions {
+                name,
+                input_file,
+                stdin,
+                timeout_secs: timeout,
+            },
+        ),
     }
+}
 
-    // コマンド出力を抑制
-    // println!("Using config file: {}", args.config.display());
+// サブコマンドの前に書かれた run のオプションは、ほかのサブコマンドでは使われないので拒否する
+fn reject_run_options(matches: &clap::ArgMatches) {
+    let Some((name, _)) = matches.subcommand() else {
+        return;
+    };
+    let mut command = Args::command();
+    let given = command
+        .get_arguments()
+        .filter(|arg| !arg.is_global_set())
+        .find(|arg| {
+            matches.value_source(arg.get_id().as_str())
+                == Some(clap::parser::ValueSource::CommandLine)
+        })
+        .map(|arg| match arg.get_long() {
+            Some(long) => format!("--{}", long),
+            None => format!("-{}", arg.get_short().unwrap_or_default()),
+        });
+    if let Some(flag) = given {
+        command
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("the subcommand '{}' cannot be used with '{}'", name, flag),
+            )
+            .exit();
+    }
+}
 
+fn run_tui(
+    config_path: PathBuf,
+    filter: TestFilter,
+    run_options: test::RunOptions,
+    reports: &[report::ReportSpec],
+    watch_options: Option<watch::WatchOptions>,
+) -> Result<()> {
     // Load and parse the configuration
-    let config = test::load_config(&args.config)
-        .with_context(|| format!("failed to load config from `{}`", args.config.display()))?;
+    let config = test::load_config(&config_path)
+        .with_context(|| format!("failed to load config from `{}`", config_path.display()))
+        .context(error::ErrorKind::ConfigInvalid)?;
+
+    // Run the tests selected by the filter
+    let mut filtered = config.clone();
+    filter.apply(&mut filtered);
+    let mut event_log = report::EventLog::open(reports, test::planned_count(&filtered))?;
+    let mut build_steps = Vec::new();
+    let outcome = test::run_tests_with(&filtered, &run_options, |event| {
+        if let test::TestEvent::BuildFinished { step } = &event {
+            build_steps.push((*step).clone());
+        }
+        event_log.record(event)
+    });
+
+    // ビルドに失敗した場合も、エラーを Build タブで見られるように TUI を開く
+    let mut build_error = None;
+    let outcome = match outcome {
+        Ok(outcome) => {
+            // 最初の実行結果をレポートとして書き出す
+            event_log.finish(&outcome)?;
+            report::write_reports(reports, &outcome)?;
+            outcome
+        }
+        Err(e) if e.downcast_ref::<error::ErrorKind>() == Some(&error::ErrorKind::BuildFailed) => {
+            build_error = Some(e);
+            test::RunOutcome::default()
+        }
+        Err(e) => return Err(e),
+    };
 
-    // Run all tests
-    let test_results = test::run_tests(&config)?;
+    // Create app state
+    let mut app = App::new(outcome.results, config, filter, run_options);
+    app.set_build_steps(build_steps);
+    app.teardown_error = outcome.teardown_error;
+    if let Some(reason) = &app.teardown_error {
+        app.show_result_popup(format!("{}\n\nSee the Build tab for its output", reason));
+    }
+    if let Some(e) = build_error {
+        app.tab_index = app::BUILD_TAB;
+        app.show_result_popup(format!(
+            "Error running tests:\n{:#}{}",
+            e,
+            build_tab_hint(&app)
+        ));
+    }
 
     // Display results in TUI
-    start_ui(test_results, config, args.config)?;
-
-    Ok(())
+    start_ui(app, config_path, watch_options)
 }
 
 fn start_ui(
-    test_results: Vec<test::TestResult>,
-    config: test::TestConfig,
-    _config_path: PathBuf,
+    mut app: App,
+    config_path: PathBuf,
+    watch_options: Option<watch::WatchOptions>,
 ) -> Result<()> {
     // Setup terminal
     enable_raw_mode()?;
@@ -77,8 +444,14 @@ fn start_ui(
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Create app state
-    let mut app = App::new(test_results, config);
+    let mut watcher = match &watch_options {
+        Some(options) => Some(watch::Watcher::new(
+            &config_path,
+            Some(&app.config),
+            options,
+        )?),
+        None => None,
+    };
 
     // ターミナルを完全に再初期化する関数
     let reset_terminal_completely = || -> Result<()> {
@@ -117,84 +490,98 @@ fn start_ui(
     loop {
         // 画面を描画
         terminal.draw(|frame| {
-            ui::render_ui::<CrosstermBackend<io::Stdout>>(frame, &app);
+            ui::render_ui(frame, &app);
         })?;
 
         // 結果ポップアップの更新（時間経過で消える）
         if app.update_result_popup() {
             // ポップアップの状態が変わったら再描画
             terminal.draw(|frame| {
-                ui::render_ui::<CrosstermBackend<io::Stdout>>(frame, &app);
+                ui::render_ui(frame, &app);
             })?;
         }
 
+        // 監視中のファイルが変わったらテストを再実行する
+        if let (Some(watcher), Some(options)) = (watcher.as_mut(), &watch_options) {
+            if let Some(changed) = watcher.poll() {
+                rerun_after_change(&mut app, watcher, &config_path, &changed, options)?;
+                continue;
+            }
+        }
+
         // Handle input with timeout
         if crossterm::event::poll(Duration::from_millis(100))? {
             if let Event::Key(key) = event::read()? {
+                // フィルタ入力中はキー入力をすべてフィルタ文字列として扱う
+                if app.editing_filter {
+                    match key.code {
+                        KeyCode::Enter => app.finish_filter_edit(),
+                        KeyCode::Esc => app.clear_filter_pattern(),
+                        KeyCode::Backspace => app.pop_filter_char(),
+                        KeyCode::Char(c) => app.push_filter_char(c),
+                        _ => {}
+                    }
+                    continue;
+                }
+
                 match key.code {
-                    KeyCode::Char('q') => {
-                        if !app.show_popup {
-                            break;
+                    KeyCode::Char('q') if !app.show_popup => break,
+                    KeyCode::Char('?') if !app.show_popup => app.toggle_help(),
+                    KeyCode::Down | KeyCode::Char('j') if !app.show_help && !app.show_popup => {
+                        if app.tab_index == 4 {
+                            // 履歴タブでは履歴を移動
+                            app.next_history();
+                        } else if app.tab_index == app::BUILD_TAB {
+                            app.next_build_entry();
+                        } else {
+                            app.next();
                         }
                     }
-                    KeyCode::Char('?') => {
-                        if !app.show_popup {
-                            app.toggle_help();
+                    KeyCode::Up | KeyCode::Char('k') if !app.show_help && !app.show_popup => {
+                        if app.tab_index == 4 {
+                            // 履歴タブでは履歴を移動
+                            app.previous_history();
+                        } else if app.tab_index == app::BUILD_TAB {
+                            app.previous_build_entry();
+                        } else {
+                            app.previous();
                         }
                     }
-                    KeyCode::Down | KeyCode::Char('j') => {
-                        if !app.show_help && !app.show_popup {
-                            if app.tab_index == 4 {
-                                // 履歴タブでは履歴を移動
-                                app.next_history();
-                            } else {
-                                app.next();
-                            }
-                        }
+                    KeyCode::Right | KeyCode::Char('l') if !app.show_help && !app.show_popup => {
+                        app.next_tab();
                     }
-                    KeyCode::Up | KeyCode::Char('k') => {
-                        if !app.show_help && !app.show_popup {
-                            if app.tab_index == 4 {
-                                // 履歴タブでは履歴を移動
-                                app.previous_history();
-                            } else {
-                                app.previous();
-                            }
-                        }
+                    KeyCode::Left | KeyCode::Char('h') if !app.show_help && !app.show_popup => {
+                        app.previous_tab();
                     }
-                    KeyCode::Right | KeyCode::Char('l') => {
-                        if !app.show_help && !app.show_popup {
-                            app.next_tab();
-                        }
+                    KeyCode::Char('r') if !app.show_help && !app.show_popup => {
+                        // ポップアップを表示
+                        app.toggle_popup(PopupType::RunTests);
                     }
-                    KeyCode::Left | KeyCode::Char('h') => {
-                        if !app.show_help && !app.show_popup {
-                            app.previous_tab();
-                        }
+                    KeyCode::Char('b') if !app.show_help && !app.show_popup => {
+                        // ポップアップを表示
+                        app.toggle_popup(PopupType::BuildToggle);
                     }
-                    KeyCode::Char('r') => {
-                        if !app.show_help && !app.show_popup {
-                            // ポップアップを表示
-                            app.toggle_popup(PopupType::RunTests);
-                        }
+                    KeyCode::Char('R') if !app.show_help && !app.show_popup => {
+                        // ポップアップを表示
+                        app.toggle_popup(PopupType::RunRelease);
                     }
-                    KeyCode::Char('b') => {
-                        if !app.show_help && !app.show_popup {
-                            // ポップアップを表示
-                            app.toggle_popup(PopupType::BuildToggle);
-                        }
+                    // 実際の出力を期待値として書き戻す
+                    KeyCode::Char('u') if !app.show_help && !app.show_popup => {
+                        app.request_bless(&config_path);
                     }
-                    KeyCode::Char('R') => {
-                        if !app.show_help && !app.show_popup {
-                            // ポップアップを表示
-                            app.toggle_popup(PopupType::RunRelease);
-                        }
+                    // 再試行されたテストの試行を切り替える
+                    KeyCode::Char(']') if !app.show_help && !app.show_popup => {
+                        app.next_attempt();
+                    }
+                    KeyCode::Char('[') if !app.show_help && !app.show_popup => {
+                        app.previous_attempt();
+                    }
+                    KeyCode::Char('/') if !app.show_help && !app.show_popup => {
+                        app.start_filter_edit();
                     }
                     // 履歴表示モードの切り替え
-                    KeyCode::Char('H') => {
-                        if !app.show_help && !app.show_popup {
-                            app.toggle_history_view();
-                        }
+                    KeyCode::Char('H') if !app.show_help && !app.show_popup => {
+                        app.toggle_history_view();
                     }
                     KeyCode::Enter => {
                         // ポップアップでの確認処理
@@ -203,19 +590,24 @@ fn start_ui(
                                 PopupType::RunTests => {
                                     // テストを再実行
                                     app.close_popup();
-                                    match test::run_tests(&app.config) {
+                                    let config = app.filtered_config();
+                                    match run_tests_in_app(&mut app, &config) {
                                         Ok(results) => {
-                                            // 現在の結果を履歴に追加
+                                            // 新しい結果を表示して履歴に追加
+                                            app.set_results(results);
                                             app.add_to_history();
 
                                             // 結果ポップアップを表示
-                                            let (passed, total, pass_rate) = app.get_stats();
+                                            let stats = app.get_stats();
                                             app.show_result_popup(format!(
-                                                "Tests completed!\n\nPassed: {}/{} ({:.1}%)",
-                                                passed, total, pass_rate
+                                                "Tests completed!\n\nPassed: {}/{} ({:.1}%)\n{}{}",
+                                                stats.ok(),
+                                                stats.executed(),
+                                                stats.pass_rate(),
+                                                stats.summary(),
+                                                teardown_hint(&app)
                                             ));
 
-                                            app.test_results = results;
                                             // UI の状態をリセット
                                             app.reset_ui_state();
 
@@ -233,8 +625,9 @@ fn start_ui(
 
                                             // エラーポップアップを表示
                                             app.show_result_popup(format!(
-                                                "Error running tests:\n{}",
-                                                e
+                                                "Error running tests:\n{:#}{}",
+                                                e,
+                                                build_tab_hint(&app)
                                             ));
                                         }
                                     }
@@ -246,19 +639,24 @@ fn start_ui(
                                         build.release = true;
                                     }
 
-                                    match test::run_tests(&app.config) {
+                                    let config = app.filtered_config();
+                                    match run_tests_in_app(&mut app, &config) {
                                         Ok(results) => {
-                                            // 現在の結果を履歴に追加
+                                            // 新しい結果を表示して履歴に追加
+                                            app.set_results(results);
                                             app.add_to_history();
 
                                             // 結果ポップアップを表示
-                                            let (passed, total, pass_rate) = app.get_stats();
+                                            let stats = app.get_stats();
                                             app.show_result_popup(format!(
-                                                "Release tests completed!\n\nPassed: {}/{} ({:.1}%)",
-                                                passed, total, pass_rate
+                                                "Release tests completed!\n\nPassed: {}/{} ({:.1}%)\n{}{}",
+                                                stats.ok(),
+                                                stats.executed(),
+                                                stats.pass_rate(),
+                                                stats.summary(),
+                                                teardown_hint(&app)
                                             ));
 
-                                            app.test_results = results;
                                             // UI の状態をリセット
                                             app.reset_ui_state();
 
@@ -275,8 +673,9 @@ fn start_ui(
 
                                             // エラーポップアップを表示
                                             app.show_result_popup(format!(
-                                                "Error running release tests:\n{}",
-                                                e
+                                                "Error running release tests:\n{:#}{}",
+                                                e,
+                                                build_tab_hint(&app)
                                             ));
                                         }
                                     }
@@ -295,13 +694,41 @@ fn start_ui(
                                     // ターミナルを再初期化
                                     reset_terminal(&mut terminal)?;
                                 }
+                                PopupType::Bless => {
+                                    let update = app.pending_bless.take();
+                                    app.close_popup();
+                                    if let Some(update) = update {
+                                        let blessed = bless::apply(
+                                            &config_path,
+                                            std::slice::from_ref(&update),
+                                        )
+                                        .and_then(|_| test::load_config(&config_path));
+                                        match blessed {
+                                            Ok(config) => {
+                                                // 読み直した設定で次の実行から新しい期待値を使う
+                                                app.config = config;
+                                                app.mark_blessed(&update.name);
+                                                app.show_result_popup(format!(
+                                                    "Updated the expected output of {}\nin {}\n\nPress r to re-run",
+                                                    update.name,
+                                                    update.target.path().display()
+                                                ));
+                                            }
+                                            Err(e) => app.show_result_popup(format!(
+                                                "Error blessing {}:\n{:#}",
+                                                update.name, e
+                                            )),
+                                        }
+                                    }
+                                    reset_terminal(&mut terminal)?;
+                                }
                                 PopupType::None | PopupType::ResultNotification => {}
                             }
                         } else if app.tab_index == 4 {
                             // 履歴タブでEnterキーを押した場合、選択した履歴を表示
-                            if let Some(history) = app.history.get(app.selected_history) {
-                                app.test_results = history.test_results.clone();
+                            if let Some(history) = app.history.get(app.selected_history).cloned() {
                                 app.release_mode = history.release_mode;
+                                app.set_results(history.test_results);
 
                                 // 結果タブに切り替え
                                 app.tab_index = 0;
@@ -345,3 +772,116 @@ fn start_ui(
 
     Ok(())
 }
+
+// --watch: 変更を検出したら設定を読み直してテストを再実行する
+fn rerun_after_change(
+    app: &mut App,
+    watcher: &mut watch::Watcher,
+    config_path: &Path,
+    changed: &[PathBuf],
+    options: &watch::WatchOptions,
+) -> Result<()> {
+    let changed_list = changed
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let config = match test::load_config(&config_path.to_path_buf()) {
+        Ok(config) => config,
+        Err(e) => {
+            app.show_result_popup(format!(
+                "Changed: {}\n\nError loading config:\n{:#}",
+                changed_list, e
+            ));
+            return Ok(());
+        }
+    };
+    watcher.update(config_path, Some(&config), options)?;
+
+    let previous_config = std::mem::replace(&mut app.config, config);
+    let filtered = app.filtered_config();
+
+    // 影響を受けたテストと前回失敗したテストを先に実行し、成功したら全体を実行する
+    let mut first = Vec::new();
+    if options.affected_first {
+        let mut failed = std::collections::HashSet::new();
+        watch::update_failed(&mut failed, &app.all_results);
+        first = watch::affected_tests(&filtered, Some(&previous_config), changed, &failed);
+    }
+
+    let outcome = if !first.is_empty() && first.len() < filtered.tests.len() {
+        match run_tests_in_app(app, &watch::subset(&filtered, &first)) {
+            Ok(results)
+                if test::TestStats::from_results(&results).has_failures()
+                    || app.teardown_error.is_some() =>
+            {
+                Ok((results, " (affected tests only)"))
+            }
+            Ok(_) => run_tests_in_app(app, &filtered).map(|results| (results, "")),
+            Err(e) => Err(e),
+        }
+    } else {
+        run_tests_in_app(app, &filtered).map(|results| (results, ""))
+    };
+
+    match outcome {
+        Ok((results, scope)) => {
+            app.set_results(results);
+            app.add_to_history();
+            let stats = app.get_stats();
+            app.show_result_popup(format!(
+                "Changed: {}\n\nPassed: {}/{} ({:.1}%){}\n{}{}",
+                changed_list,
+                stats.ok(),
+                stats.executed(),
+                stats.pass_rate(),
+                scope,
+                stats.summary(),
+                teardown_hint(app)
+            ));
+        }
+        Err(e) => {
+            app.show_result_popup(format!(
+                "Changed: {}\n\nError running tests:\n{:#}{}",
+                changed_list,
+                e,
+                build_tab_hint(app)
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// テストを実行し、ビルドの出力を Build タブに表示できるように保存する
+fn run_tests_in_app(app: &mut App, config: &test::TestConfig) -> Result<Vec<test::TestResult>> {
+    let mut steps = Vec::new();
+    let outcome = test::run_tests_with(config, &app.run_options, |event| {
+        if let test::TestEvent::BuildFinished { step } = event {
+            steps.push(step.clone());
+        }
+    });
+    app.set_build_steps(steps);
+    let outcome = outcome?;
+    app.teardown_error = outcome.teardown_error;
+    Ok(outcome.results)
+}
+
+// スイートの teardown が失敗していれば結果のポップアップで知らせる
+fn teardown_hint(app: &App) -> &'static str {
+    if app.teardown_error.is_some() {
+        "\n\nThe suite teardown failed; see the Build tab for its output"
+    } else {
+        ""
+    }
+}
+
+// ビルドが失敗していればエラーのポップアップで Build タブを案内する
+fn build_tab_hint(app: &App) -> &'static str {
+    if app.build_steps.iter().any(|step| !step.success) {
+        "\n\nSee the Build tab for the build output"
+    } else {
+        ""
+    }
+}
@@ -0,0 +1,115 @@
+// src/output/github.rs
+use super::{Formatter, RunInfo};
+use crate::test::{self, DiffLine, RunOutcome, TestResult, TestStatus};
+use std::path::PathBuf;
+
+/// Prints GitHub Actions workflow commands so failures show up on pull requests
+///
+/// The annotations point at the `name` line of the test in the config file.
+#[derive(Debug, Default)]
+pub struct GithubFormatter {
+    config_path: PathBuf,
+    source: String,
+}
+
+impl Formatter for GithubFormatter {
+    fn start(&mut self, run: &RunInfo) {
+        self.config_path = run.config_path.to_path_buf();
+        // 読めなくても行番号なしで注釈を出す
+        self.source = std::fs::read_to_string(run.config_path).unwrap_or_default();
+    }
+
+    fn finish(&mut self, outcome: &RunOutcome) {
+        for result in &outcome.results {
+            let (level, message) = match result.status {
+                TestStatus::Failed => ("error", failure_message(result)),
+                TestStatus::XPassed => (
+                    "error",
+                    "Marked as xfail but the output matched; remove the marker.".to_string(),
+                ),
+                TestStatus::Error | TestStatus::HookFailed => {
+                    ("error", result.reason.clone().unwrap_or_default())
+                }
+                TestStatus::Flaky => (
+                    "warning",
+                    format!("Passed on attempt {}", result.attempts.len()),
+                ),
+                _ => continue,
+            };
+
+            let mut properties = vec![("file", self.config_path.display().to_string())];
+            if let Some(line) = test::find_test_line(&self.source, &result.name) {
+                properties.push(("line", line.to_string()));
+            }
+            properties.push((
+                "title",
+                format!("{} {}", result.status.label(), result.name),
+            ));
+
+            let properties: Vec<String> = properties
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, escape_property(value)))
+                .collect();
+
+            println!(
+                "::{} {}::{}",
+                level,
+                properties.join(","),
+                escape_data(&message)
+            );
+        }
+
+        // スイートの teardown の失敗は設定ファイル全体への注釈にする
+        if let Some(reason) = &outcome.teardown_error {
+            println!(
+                "::error file={},title={}::{}",
+                escape_property(&self.config_path.display().to_string()),
+                escape_property("Suite teardown failed"),
+                escape_data(reason)
+            );
+        }
+    }
+}
+
+fn failure_message(result: &TestResult) -> String {
+    let mut message = match &result.reason {
+        Some(reason) => format!("{}\n", reason),
+        None if result.diff.is_none() => {
+            "Checks of the files, screen or dialog failed\n".to_string()
+        }
+        None => "Output did not match the expected output\n".to_string(),
+    };
+    message.push_str(&format!("$ {} {}\n", result.command, result.args.join(" ")));
+    push_diff(&mut message, result.diff.as_deref().unwrap_or_default());
+    for check in result.failed_checks() {
+        message.push_str(&format!("{}\n", check.summary()));
+        push_diff(&mut message, check.diff.unwrap_or_default());
+    }
+    message
+}
+
+fn push_diff(message: &mut String, diff: &[DiffLine]) {
+    for line in diff {
+        let sign = match line.tag {
+            similar::ChangeTag::Delete => '-',
+            similar::ChangeTag::Insert => '+',
+            similar::ChangeTag::Equal => ' ',
+        };
+        message.push_str(&format!(
+            "{}{}\n",
+            sign,
+            line.content.trim_end_matches('\n')
+        ));
+    }
+}
+
+// ワークフローコマンドのエスケープ規則
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
@@ -0,0 +1,54 @@
+// src/output/mod.rs
+mod github;
+mod tap;
+
+pub use github::GithubFormatter;
+pub use tap::TapFormatter;
+
+use crate::filter::TestFilter;
+use crate::test::{RunOutcome, TestEvent};
+use std::path::Path;
+
+/// How a headless run prints its results to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Colored text for humans
+    #[default]
+    Text,
+    /// TAP version 14
+    Tap,
+    /// Colored text plus GitHub Actions annotations for the failing tests
+    Github,
+}
+
+/// How much the text output prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verbosity {
+    /// Only the failing tests and the totals
+    Quiet,
+    #[default]
+    Normal,
+    /// Also the command, stdout and stderr of the passing tests
+    Verbose,
+}
+
+/// What is known about a run before its first test starts
+pub struct RunInfo<'a> {
+    pub config_path: &'a Path,
+    pub filter: &'a TestFilter,
+    /// Tests left after the filter
+    pub selected: usize,
+    /// Tests marked `only`
+    pub focused: usize,
+    /// Tests that will get a result
+    pub planned: usize,
+}
+
+/// Prints a headless run while it progresses
+pub trait Formatter {
+    fn start(&mut self, _run: &RunInfo) {}
+
+    fn event(&mut self, _event: TestEvent) {}
+
+    fn finish(&mut self, outcome: &RunOutcome);
+}
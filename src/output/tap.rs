@@ -0,0 +1,156 @@
+// src/output/tap.rs
+use super::{Formatter, RunInfo};
+use crate::test::{RunOutcome, TestEvent, TestResult, TestStatus};
+use serde_yaml::{Mapping, Value};
+
+/// Prints TAP version 14, one test point per test as soon as it finishes and the plan at the end
+#[derive(Debug, Default)]
+pub struct TapFormatter;
+
+impl Formatter for TapFormatter {
+    fn start(&mut self, run: &RunInfo) {
+        println!("TAP version 14");
+        println!("# {}", run.config_path.display());
+        if !run.filter.is_empty() {
+            println!("# filter: {}", run.filter.describe());
+        }
+    }
+
+    fn event(&mut self, event: TestEvent) {
+        if let TestEvent::Finished { index, result } = event {
+            print_test_point(index + 1, result);
+        }
+    }
+
+    fn finish(&mut self, outcome: &RunOutcome) {
+        // 打ち切られた場合も全テストに結果があるが、計画は結果の数に合わせて最後に出す
+        println!("1..{}", outcome.results.len());
+        // スイートの teardown はテストではないので、失敗は診断のコメントとして出す
+        if let Some(reason) = &outcome.teardown_error {
+            for line in reason.lines() {
+                println!("{}", format!("# {}", line).trim_end());
+            }
+        }
+        let stats = crate::test::TestStats::from_results(&outcome.results);
+        println!("# {}", stats.summary());
+    }
+}
+
+fn print_test_point(number: usize, result: &TestResult) {
+    let reason = result.reason.as_deref().unwrap_or_default();
+    let (ok, directive) = match result.status {
+        TestStatus::Passed | TestStatus::Flaky => (true, String::new()),
+        TestStatus::Failed | TestStatus::XPassed | TestStatus::Error | TestStatus::HookFailed => {
+            (false, String::new())
+        }
+        TestStatus::Skipped => (true, format!(" # SKIP {}", reason)),
+        TestStatus::NotRun => (true, format!(" # SKIP not run: {}", reason)),
+        // xfail は TAP の TODO と同じ意味
+        TestStatus::XFailed => (false, format!(" # TODO {}", reason)),
+    };
+
+    println!(
+        "{} {} - {}{}",
+        if ok { "ok" } else { "not ok" },
+        number,
+        escape(&result.name),
+        escape_directive(directive.trim_end())
+    );
+
+    if let Some(diagnostics) = diagnostics(result) {
+        println!("  ---");
+        for line in diagnostics.lines() {
+            println!("  {}", line);
+        }
+        println!("  ...");
+    }
+}
+
+/// YAML diagnostics for the test points worth a closer look
+fn diagnostics(result: &TestResult) -> Option<String> {
+    let message = match result.status {
+        TestStatus::Failed if result.attempts.last().is_some_and(|a| a.timed_out) => {
+            result.reason.clone().unwrap_or_default()
+        }
+        TestStatus::Failed if result.diff.is_none() => {
+            "checks of the files, screen or dialog failed".to_string()
+        }
+        TestStatus::Failed => "output did not match the expected output".to_string(),
+        TestStatus::XPassed => "marked as xfail but the output matched".to_string(),
+        TestStatus::Error | TestStatus::HookFailed => result.reason.clone().unwrap_or_default(),
+        TestStatus::Flaky => format!("passed on attempt {}", result.attempts.len()),
+        _ => return None,
+    };
+
+    let mut map = Mapping::new();
+    map.insert("message".into(), message.into());
+    map.insert(
+        "severity".into(),
+        if result.status == TestStatus::Flaky {
+            "todo"
+        } else {
+            "fail"
+        }
+        .into(),
+    );
+    map.insert(
+        "command".into(),
+        format!("{} {}", result.command, result.args.join(" "))
+            .trim_end()
+            .into(),
+    );
+    map.insert(
+        "duration_ms".into(),
+        Value::Number((result.execution_time.as_millis() as u64).into()),
+    );
+    if result.attempts.len() > 1 {
+        map.insert(
+            "attempts".into(),
+            Value::Number((result.attempts.len() as u64).into()),
+        );
+    }
+    // ファイルやスナップショットだけが違った場合は出力を比べない
+    if !matches!(
+        result.status,
+        TestStatus::Error | TestStatus::HookFailed | TestStatus::Flaky
+    ) && (result.diff.is_some() || result.failed_checks().is_empty())
+    {
+        map.insert("expected".into(), expected_output(result).into());
+        map.insert("got".into(), result.actual_output.clone().into());
+    }
+    let checks: Vec<Value> = result
+        .failed_checks()
+        .iter()
+        .map(|check| check.summary().into())
+        .collect();
+    if !checks.is_empty() {
+        map.insert("checks".into(), Value::Sequence(checks));
+    }
+    if !result.stderr.is_empty() {
+        map.insert("stderr".into(), result.stderr.clone().into());
+    }
+
+    serde_yaml::to_string(&map).ok()
+}
+
+/// The expected output, rebuilt from the equal and deleted lines of the diff
+fn expected_output(result: &TestResult) -> String {
+    result
+        .diff
+        .iter()
+        .flatten()
+        .filter(|line| line.tag != similar::ChangeTag::Insert)
+        .map(|line| line.content.as_str())
+        .collect()
+}
+
+// TAP 14 では説明文中の `#` と `\` をエスケープする
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('#', "\\#")
+        .replace(['\n', '\r'], " ")
+}
+
+fn escape_directive(directive: &str) -> String {
+    directive.replace(['\n', '\r'], " ")
+}
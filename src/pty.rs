@@ -0,0 +1,643 @@
+// src/pty.rs
+use crate::test::{DialogEvent, DialogStep, ScreenStep};
+use anyhow::{Context, Result};
+use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Terminal size used when `rows` or `cols` is not set
+pub const DEFAULT_ROWS: u16 = 24;
+pub const DEFAULT_COLS: u16 = 80;
+
+/// How long a dialog step waits for its `expect` when `timeout_ms` is not set
+pub const DEFAULT_EXPECT_TIMEOUT_MS: u64 = 10_000;
+
+/// Keys that can be written by name in the `key` of a screen step
+pub const KEY_NAMES: &[&str] = &[
+    "enter",
+    "tab",
+    "esc",
+    "backspace",
+    "delete",
+    "up",
+    "down",
+    "right",
+    "left",
+    "home",
+    "end",
+    "pageup",
+    "pagedown",
+];
+
+/// What a command wrote to its terminal
+#[derive(Debug)]
+pub struct PtyOutput {
+    /// Raw bytes written to the terminal, including escape sequences
+    pub bytes: Vec<u8>,
+    pub duration: Duration,
+    pub timed_out: bool,
+}
+
+impl PtyOutput {
+    /// The output as text, with the terminal's `\r\n` line endings turned into `\n`
+    pub fn text(&self, strip_ansi: bool) -> String {
+        to_text(&self.bytes, strip_ansi)
+    }
+}
+
+/// The screens of a command driven by a script of keystrokes
+#[derive(Debug)]
+pub struct ScreenOutput {
+    /// The screen once the command exited (or was killed)
+    pub screen: String,
+    /// Screens taken by the `snapshot` steps, in the order of the script
+    pub snapshots: Vec<(String, String)>,
+    pub duration: Duration,
+    pub timed_out: bool,
+}
+
+/// The output of a command and how far its dialog went
+#[derive(Debug)]
+pub struct DialogOutput {
+    /// Raw bytes written to the terminal, including escape sequences
+    pub bytes: Vec<u8>,
+    /// The steps that ran, up to the first that failed
+    pub transcript: Vec<DialogEvent>,
+    pub duration: Duration,
+    pub timed_out: bool,
+}
+
+impl DialogOutput {
+    /// The output as text, like [`PtyOutput::text`]
+    pub fn text(&self, strip_ansi: bool) -> String {
+        to_text(&self.bytes, strip_ansi)
+    }
+}
+
+/// A command running in a pseudo-terminal, with its screen emulated as a VT100 terminal
+pub struct Session {
+    child: Box<dyn Child + Send + Sync>,
+    // 閉じると子プロセスの端末も閉じるので、終わるまで持っておく
+    _master: Box<dyn MasterPty + Send>,
+    writer: Box<dyn Write + Send>,
+    receiver: Receiver<Vec<u8>>,
+    parser: vt100::Parser,
+    bytes: Vec<u8>,
+    start_time: Instant,
+    deadline: Instant,
+    exited: bool,
+}
+
+impl Session {
+    /// Start a command in a new terminal of `rows` x `cols`; it is killed after `timeout`
+    ///
+    /// The command runs in `dir` (the working directory if `None`).
+    pub fn spawn(
+        program: &str,
+        args: &[String],
+        dir: Option<&Path>,
+        (rows, cols): (u16, u16),
+        timeout: Duration,
+    ) -> Result<Self> {
+        let pair = native_pty_system()
+            .openpty(PtySize {
+                rows,
+                cols,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .map_err(|e| anyhow::anyhow!("Failed to open a pseudo-terminal: {}", e))?;
+
+        let mut command = CommandBuilder::new(program);
+        command.args(args);
+        // 指定しないとホームディレクトリで実行される
+        let cwd = match dir {
+            Some(dir) => dir.to_path_buf(),
+            None => std::env::current_dir()?,
+        };
+        command.cwd(cwd);
+        if command.get_env("TERM").is_none() {
+            command.env("TERM", "xterm-256color");
+        }
+
+        let start_time = Instant::now();
+        let child = pair
+            .slave
+            .spawn_command(command)
+            .map_err(|e| anyhow::anyhow!("Failed to spawn command: {}: {}", program, e))?;
+        // 子プロセスが終わったときに読み込みが終わるよう、こちら側の端末は閉じる
+        drop(pair.slave);
+
+        // 出力は別スレッドで読み続ける（読み込みは子プロセスの終了まで戻らない）
+        let mut reader = pair
+            .master
+            .try_clone_reader()
+            .map_err(|e| anyhow::anyhow!("Failed to read from the pseudo-terminal: {}", e))?;
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut buffer = [0; 8192];
+            while let Ok(n) = reader.read(&mut buffer) {
+                if n == 0 || sender.send(buffer[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        let writer = pair
+            .master
+            .take_writer()
+            .map_err(|e| anyhow::anyhow!("Failed to write to the pseudo-terminal: {}", e))?;
+
+        Ok(Session {
+            child,
+            _master: pair.master,
+            writer,
+            receiver,
+            parser: vt100::Parser::new(rows, cols, 0),
+            bytes: Vec::new(),
+            start_time,
+            deadline: start_time + timeout,
+            exited: false,
+        })
+    }
+
+    /// Type bytes into the terminal; ignored once the command exited
+    pub fn send(&mut self, bytes: &[u8]) -> Result<()> {
+        if self.exited {
+            return Ok(());
+        }
+        let result = self
+            .writer
+            .write_all(bytes)
+            .and_then(|_| self.writer.flush());
+        // 書き込みの間に終了した場合は、残りの入力を捨てる
+        if result.is_err() && self.child.try_wait()?.is_some() {
+            self.exited = true;
+            return Ok(());
+        }
+        result.context("Failed to write to the pseudo-terminal")
+    }
+
+    /// Read the output for `duration`, stopping early at the timeout of the command
+    pub fn pump(&mut self, duration: Duration) {
+        while let Ok(chunk) = self.receiver.try_recv() {
+            self.feed(chunk);
+        }
+        let until = (Instant::now() + duration).min(self.deadline);
+        while let Some(left) = until.checked_duration_since(Instant::now()) {
+            match self.receiver.recv_timeout(left) {
+                Ok(chunk) => self.feed(chunk),
+                Err(RecvTimeoutError::Timeout) => break,
+                // 端末が閉じられたら、残りの時間は待つだけ
+                Err(RecvTimeoutError::Disconnected) => {
+                    std::thread::sleep(left);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Wait for the next output, until `until` at most; returns `false` if none came
+    ///
+    /// Returns at once when the command closed its terminal.
+    pub fn receive(&mut self, until: Instant) -> bool {
+        let left = until
+            .min(self.deadline)
+            .checked_duration_since(Instant::now());
+        match left.map(|left| self.receiver.recv_timeout(left)) {
+            Some(Ok(chunk)) => {
+                self.feed(chunk);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the timeout of the command ran out
+    pub fn is_out_of_time(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// The output so far, with escape sequences
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The rendered screen, as [`normalize_screen`] formats it
+    pub fn screen(&self) -> String {
+        let screen = self.parser.screen();
+        let (_, cols) = screen.size();
+        let rows: Vec<String> = screen.rows(0, cols).collect();
+        normalize_screen(&rows.join("\n"))
+    }
+
+    /// The bytes a named key sends, following the cursor key mode of the command
+    pub fn key(&self, name: &str) -> Option<Vec<u8>> {
+        let application = self.parser.screen().application_cursor();
+        let cursor = |code: &str| {
+            let prefix = if application { "\x1bO" } else { "\x1b[" };
+            Some(format!("{}{}", prefix, code).into_bytes())
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "enter" => Some(b"\r".to_vec()),
+            "tab" => Some(b"\t".to_vec()),
+            "esc" => Some(b"\x1b".to_vec()),
+            "backspace" => Some(b"\x7f".to_vec()),
+            "delete" => Some(b"\x1b[3~".to_vec()),
+            "up" => cursor("A"),
+            "down" => cursor("B"),
+            "right" => cursor("C"),
+            "left" => cursor("D"),
+            "home" => cursor("H"),
+            "end" => cursor("F"),
+            "pageup" => Some(b"\x1b[5~".to_vec()),
+            "pagedown" => Some(b"\x1b[6~".to_vec()),
+            name => ctrl_key(name).map(|byte| vec![byte]),
+        }
+    }
+
+    /// Stop the command, unless it already exited
+    pub fn kill(&mut self) -> Result<()> {
+        if self.child.try_wait()?.is_none() {
+            self.child.kill()?;
+            self.child.wait()?;
+        }
+        Ok(())
+    }
+
+    /// Wait for the command to exit, killing it at the timeout; returns whether it timed out
+    pub fn finish(&mut self) -> Result<(bool, Duration)> {
+        let timed_out = loop {
+            while let Ok(chunk) = self.receiver.try_recv() {
+                self.feed(chunk);
+            }
+            if self.child.try_wait()?.is_some() {
+                break false;
+            }
+            if self.is_out_of_time() {
+                self.child.kill()?;
+                self.child.wait()?;
+                break true;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        let duration = self.start_time.elapsed();
+        self.exited = true;
+
+        // 終了後に残っている出力を読む（端末を開いたままの孫プロセスは待たない）
+        while let Ok(chunk) = self.receiver.recv_timeout(Duration::from_millis(200)) {
+            self.feed(chunk);
+        }
+        Ok((timed_out, duration))
+    }
+
+    fn feed(&mut self, chunk: Vec<u8>) {
+        self.parser.process(&chunk);
+        self.bytes.extend(chunk);
+    }
+}
+
+/// A screen as text: each row ends with a newline and has no trailing spaces, and the blank
+/// rows at the bottom are left out
+///
+/// Leading spaces are kept, since they place the text on the screen.
+pub fn normalize_screen(text: &str) -> String {
+    let mut rows: Vec<&str> = text.lines().map(str::trim_end).collect();
+    while rows.last().is_some_and(|row| row.is_empty()) {
+        rows.pop();
+    }
+    rows.iter().map(|row| format!("{}\n", row)).collect()
+}
+
+// 端末の出力をテキストにする（端末の改行 \r\n は \n にする）
+fn to_text(bytes: &[u8], strip_ansi: bool) -> String {
+    let bytes = if strip_ansi {
+        strip_ansi_escapes::strip(bytes)
+    } else {
+        bytes.to_vec()
+    };
+    String::from_utf8_lossy(&bytes).replace("\r\n", "\n")
+}
+
+/// The output of a dialog as plain text, decoded as it arrives
+///
+/// Text is only ever appended, so positions in it stay valid. Bytes at the end that may be the
+/// start of a character or of an escape sequence are kept back until the rest arrives.
+#[derive(Default)]
+struct PlainText {
+    text: String,
+    /// Number of bytes of the output decoded so far
+    decoded: usize,
+}
+
+impl PlainText {
+    /// Decode the complete part of the new bytes of `output`, the whole output so far
+    fn update(&mut self, output: &[u8]) -> &str {
+        let pending = &output[self.decoded..];
+        let end = complete_len(pending);
+        self.push(&pending[..end]);
+        &self.text
+    }
+
+    /// Decode everything, including bytes kept back
+    fn flush(&mut self, output: &[u8]) -> &str {
+        let pending = &output[self.decoded..];
+        self.push(pending);
+        &self.text
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.text.push_str(&to_text(bytes, true));
+        self.decoded += bytes.len();
+    }
+}
+
+// 末尾の、続きがまだ届いていない UTF-8 の文字とエスケープシーケンスを除いた長さ
+fn complete_len(bytes: &[u8]) -> usize {
+    let mut end = bytes.len();
+    if let Some(escape) = bytes.iter().rposition(|&b| b == 0x1b) {
+        if !is_complete_escape(&bytes[escape..]) {
+            end = escape;
+        }
+    }
+    // 末尾 3 バイト以内にある多バイト文字の先頭バイトから、文字が揃っているかを見る
+    let start = end.saturating_sub(3);
+    if let Some(lead) = (start..end).rev().find(|&i| bytes[i] >= 0xc0) {
+        let len = match bytes[lead] {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            _ => 4,
+        };
+        if end - lead < len {
+            end = lead;
+        }
+    }
+    end
+}
+
+// ESC から始まるシーケンスが終わりまで届いているか
+fn is_complete_escape(sequence: &[u8]) -> bool {
+    match sequence.get(1) {
+        None => false,
+        // CSI: パラメータの後の終端バイトで終わる
+        Some(b'[') => sequence[2..].iter().any(|b| (0x40..=0x7e).contains(b)),
+        // OSC などの文字列: BEL で終わる（ESC \ で終わる場合は最後の ESC がそちらになる）
+        Some(b']' | b'P' | b'X' | b'^' | b'_') => sequence.contains(&0x07),
+        // 文字集合の指定は 3 バイト
+        Some(b'(' | b')' | b'*' | b'+' | b'#' | b'%') => sequence.len() >= 3,
+        Some(_) => true,
+    }
+}
+
+/// `ctrl-c` style key names: the control character of the letter
+pub fn ctrl_key(name: &str) -> Option<u8> {
+    let letter = name.to_ascii_lowercase().strip_prefix("ctrl-")?.to_string();
+    match letter.as_bytes() {
+        [c @ b'a'..=b'z'] => Some(c - b'a' + 1),
+        _ => None,
+    }
+}
+
+/// Run a command in a new pseudo-terminal of the given size
+///
+/// `input` is typed into the terminal followed by end-of-file, so it is echoed like in a real
+/// terminal. The command runs in `dir` (the working directory if `None`).
+pub fn run(
+    program: &str,
+    args: &[String],
+    dir: Option<&Path>,
+    input: Option<&str>,
+    size: (u16, u16),
+    timeout: Duration,
+) -> Result<PtyOutput> {
+    let mut session = Session::spawn(program, args, dir, size, timeout)?;
+    if let Some(input) = input {
+        session.send(input.as_bytes())?;
+        // 行の途中の Ctrl-D は行を送るだけなので、その場合は 2 回送る
+        let eof: &[u8] = if input.is_empty() || input.ends_with('\n') {
+            b"\x04"
+        } else {
+            b"\x04\x04"
+        };
+        session.send(eof)?;
+    }
+
+    let (timed_out, duration) = session.finish()?;
+    Ok(PtyOutput {
+        bytes: session.bytes().to_vec(),
+        duration,
+        timed_out,
+    })
+}
+
+/// Run a command in a new pseudo-terminal, typing the keys of the script
+///
+/// Each step sends its text or key, waits, and then takes its snapshot. After the last step the
+/// command is expected to exit by itself before the timeout.
+pub fn run_screen(
+    program: &str,
+    args: &[String],
+    dir: Option<&Path>,
+    size: (u16, u16),
+    steps: &[ScreenStep],
+    timeout: Duration,
+) -> Result<ScreenOutput> {
+    let mut session = Session::spawn(program, args, dir, size, timeout)?;
+    let mut snapshots = Vec::new();
+    for step in steps {
+        if session.is_out_of_time() {
+            break;
+        }
+        if let Some(text) = &step.send {
+            session.send(text.as_bytes())?;
+        }
+        if let Some(name) = &step.key {
+            let bytes = session
+                .key(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown key: {}", name))?;
+            session.send(&bytes)?;
+        }
+        session.pump(Duration::from_millis(step.wait_ms.unwrap_or(0)));
+        if let Some(name) = &step.snapshot {
+            snapshots.push((name.clone(), session.screen()));
+        }
+    }
+
+    let (timed_out, duration) = session.finish()?;
+    Ok(ScreenOutput {
+        screen: session.screen(),
+        snapshots,
+        duration,
+        timed_out,
+    })
+}
+
+/// Run a command in a new pseudo-terminal, answering its prompts as the dialog says
+///
+/// Each step waits until the output since the previous match matches its `expect`, with escape
+/// sequences removed, and then types its `send`. The dialog stops at the first step that does
+/// not match in time, and the command is stopped; otherwise the command is expected to exit by
+/// itself after the last step.
+pub fn run_dialog(
+    program: &str,
+    args: &[String],
+    dir: Option<&Path>,
+    size: (u16, u16),
+    steps: &[DialogStep],
+    timeout: Duration,
+) -> Result<DialogOutput> {
+    let mut session = Session::spawn(program, args, dir, size, timeout)?;
+    let mut transcript = Vec::new();
+    let mut output = PlainText::default();
+    // 前の一致より後の出力だけを次の expect の対象にする
+    let mut from = 0;
+    for step in steps {
+        let mut event = DialogEvent {
+            expect: step.expect.clone(),
+            send: step.send.clone(),
+            success: true,
+            output: String::new(),
+            message: None,
+        };
+        if let Some(pattern) = &step.expect {
+            let regex = regex::Regex::new(pattern)
+                .with_context(|| format!("Invalid dialog regex: {}", pattern))?;
+            let wait = step.timeout_ms.unwrap_or(DEFAULT_EXPECT_TIMEOUT_MS);
+            let until = Instant::now()
+                .checked_add(Duration::from_millis(wait))
+                .unwrap_or(session.deadline);
+            loop {
+                let rest = &output.update(session.bytes())[from..];
+                if let Some(found) = regex.find(rest) {
+                    event.output = rest[..found.end()].to_string();
+                    from += found.end();
+                    break;
+                }
+                if !session.receive(until) {
+                    // 途中で止まった文字やエスケープシーケンスも含めて、読んだ出力をすべて見せる
+                    event.success = false;
+                    event.output = output.flush(session.bytes())[from..].to_string();
+                    event.message = Some(if session.is_out_of_time() {
+                        format!("test timed out waiting for /{}/", pattern)
+                    } else if Instant::now() >= until {
+                        format!("no match for /{}/ within {} ms", pattern, wait)
+                    } else {
+                        format!("command exited before /{}/ matched", pattern)
+                    });
+                    break;
+                }
+            }
+        }
+        if !event.success {
+            transcript.push(event);
+            // 応答を待っているコマンドは終わらないので止める
+            if !session.is_out_of_time() {
+                session.kill()?;
+            }
+            break;
+        }
+        if let Some(text) = &step.send {
+            session.send(text.as_bytes())?;
+        }
+        transcript.push(event);
+    }
+
+    let (timed_out, duration) = session.finish()?;
+    Ok(DialogOutput {
+        bytes: session.bytes().to_vec(),
+        transcript,
+        duration,
+        timed_out,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ctrl_key_maps_letters_to_control_characters() {
+        assert_eq!(ctrl_key("ctrl-a"), Some(0x01));
+        assert_eq!(ctrl_key("ctrl-c"), Some(0x03));
+        assert_eq!(ctrl_key("CTRL-D"), Some(0x04));
+        assert_eq!(ctrl_key("ctrl-z"), Some(0x1a));
+    }
+
+    #[test]
+    fn ctrl_key_rejects_other_names() {
+        assert_eq!(ctrl_key("enter"), None);
+        assert_eq!(ctrl_key("ctrl-"), None);
+        assert_eq!(ctrl_key("ctrl-ab"), None);
+        assert_eq!(ctrl_key("ctrl-1"), None);
+        assert_eq!(ctrl_key("alt-a"), None);
+    }
+
+    #[test]
+    fn normalize_screen_trims_row_ends_and_blank_bottom_rows() {
+        assert_eq!(
+            normalize_screen("  menu   \n> item\n\n   \n"),
+            "  menu\n> item\n"
+        );
+    }
+
+    #[test]
+    fn normalize_screen_keeps_blank_rows_between_text() {
+        assert_eq!(normalize_screen("top\n\n\nbottom"), "top\n\n\nbottom\n");
+        assert_eq!(normalize_screen("\n\nlow"), "\n\nlow\n");
+    }
+
+    #[test]
+    fn normalize_screen_of_a_blank_screen_is_empty() {
+        assert_eq!(normalize_screen(""), "");
+        assert_eq!(normalize_screen("   \n   \n"), "");
+    }
+
+    #[test]
+    fn complete_len_keeps_back_unfinished_characters() {
+        let text = "é".as_bytes();
+        assert_eq!(complete_len(b"abc"), 3);
+        assert_eq!(complete_len(text), 2);
+        assert_eq!(complete_len(&text[..1]), 0);
+        assert_eq!(complete_len("a→".as_bytes()), 4);
+        assert_eq!(complete_len(&"a→".as_bytes()[..3]), 1);
+    }
+
+    #[test]
+    fn complete_len_keeps_back_unfinished_escape_sequences() {
+        assert_eq!(complete_len(b"ok\x1b"), 2);
+        assert_eq!(complete_len(b"ok\x1b[1;3"), 2);
+        assert_eq!(complete_len(b"ok\x1b[1;31m"), 9);
+        assert_eq!(complete_len(b"ok\x1b]0;title"), 2);
+        assert_eq!(complete_len(b"ok\x1b]0;title\x07"), 12);
+        assert_eq!(complete_len(b"ok\x1b]0;title\x1b\\"), 13);
+        assert_eq!(complete_len(b"ok\x1b("), 2);
+        assert_eq!(complete_len(b"ok\x1b(B"), 5);
+    }
+
+    #[test]
+    fn plain_text_positions_survive_split_reads() {
+        let output = "名前: \x1b[1mtaro\x1b[0m\r\n次: ".as_bytes();
+        let mut text = PlainText::default();
+        let mut seen = Vec::new();
+        // 1 バイトずつ届いても、前に返したテキストは後から変わらない
+        for end in 1..=output.len() {
+            let current = text.update(&output[..end]).to_string();
+            assert!(current.starts_with(seen.last().map_or("", String::as_str)));
+            seen.push(current);
+        }
+        assert_eq!(text.update(output), "名前: taro\n次: ");
+    }
+
+    #[test]
+    fn plain_text_flush_includes_bytes_kept_back() {
+        let mut output = b"abc\x1b[1".to_vec();
+        let mut text = PlainText::default();
+        assert_eq!(text.update(&output), "abc");
+        assert_eq!(text.decoded, 3);
+        text.flush(&output);
+        assert_eq!(text.decoded, output.len());
+        output.extend_from_slice(b"def");
+        assert!(text.update(&output).ends_with("def"));
+    }
+}
@@ -0,0 +1,376 @@
+// src/record.rs
+use crate::bless;
+use crate::test::{CargoBin, Marker, TestCase, TestConfig};
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Append a test to a configuration file, creating the file if needed
+///
+/// The test is written in the format of the file; the rest of the file is left untouched.
+pub fn append_test(config_path: &Path, test_case: &TestCase) -> Result<()> {
+    let source = if config_path.exists() {
+        std::fs::read_to_string(config_path)
+            .with_context(|| format!("Failed to read config file: {}", config_path.display()))?
+    } else {
+        String::new()
+    };
+    let updated = add_test(config_path, &source, test_case)?;
+
+    if let Some(parent) = config_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    std::fs::write(config_path, updated)
+        .with_context(|| format!("Failed to write config file: {}", config_path.display()))
+}
+
+/// Add a test at the end of the source of a configuration file
+///
+/// The format is chosen by the extension of `config_path`.
+pub fn add_test(config_path: &Path, source: &str, test_case: &TestCase) -> Result<String> {
+    let extension = config_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+
+    let (updated, config): (String, TestConfig) = match extension.as_str() {
+        "toml" => {
+            let updated = append_toml_test(source, test_case)?;
+            let config = toml::from_str(&updated)?;
+            (updated, config)
+        }
+        "yaml" | "yml" => {
+            let updated = append_yaml_test(source, test_case)?;
+            let config = serde_yaml::from_str(&updated)?;
+            (updated, config)
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Unsupported configuration format: {}. Please use .yaml, .yml, or .toml files.",
+                extension
+            ))
+        }
+    };
+
+    // 読み直したテストが追加したものと同じか確かめる
+    let written = config.tests.iter().rev().find(|t| t.name == test_case.name);
+    if written.and_then(|t| serde_yaml::to_string(t).ok()) != serde_yaml::to_string(test_case).ok()
+    {
+        return Err(anyhow::anyhow!(
+            "Could not add the test to {} safely; please add it by hand",
+            config_path.display()
+        ));
+    }
+    Ok(updated)
+}
+
+fn append_toml_test(source: &str, test_case: &TestCase) -> Result<String> {
+    let mut document: toml_edit::DocumentMut = source.parse().context("TOML parse error")?;
+    let tests = document
+        .entry("tests")
+        .or_insert(toml_edit::Item::ArrayOfTables(Default::default()))
+        .as_array_of_tables_mut()
+        .ok_or_else(|| anyhow::anyhow!("`tests` must be written as [[tests]] tables"))?;
+
+    let mut table = toml_edit::Table::new();
+    table.insert("name", toml_edit::value(&test_case.name));
+    match &test_case.cargo_bin {
+        Some(cargo_bin) => {
+            let mut inline = toml_edit::InlineTable::new();
+            for (key, value) in cargo_bin_fields(cargo_bin) {
+                inline.insert(key, value.into());
+            }
+            table.insert("cargo_bin", toml_edit::value(inline));
+        }
+        None => {
+            table.insert("command", toml_edit::value(&test_case.command));
+        }
+    }
+    if let Some(args) = &test_case.args {
+        table.insert("args", toml_edit::value(string_array(args)));
+    }
+    if let Some(input) = &test_case.input {
+        table.insert("input", toml_edit::value(input));
+    }
+    if let Some(path) = &test_case.input_file {
+        table.insert("input_file", toml_edit::value(path.display().to_string()));
+    }
+    match (&test_case.expected_output_file, &test_case.expected_output) {
+        (Some(path), _) => {
+            table.insert(
+                "expected_output_file",
+                toml_edit::value(path.display().to_string()),
+            );
+        }
+        (None, Some(output)) => {
+            table.insert("expected_output", toml_edit::value(output));
+        }
+        (None, None) => {}
+    }
+    if let Some(timeout) = test_case.timeout_secs {
+        table.insert("timeout_secs", toml_edit::value(timeout as i64));
+    }
+    match &test_case.skip {
+        Some(Marker::Flag(flag)) => {
+            table.insert("skip", toml_edit::value(*flag));
+        }
+        Some(Marker::Reason(reason)) => {
+            table.insert("skip", toml_edit::value(reason));
+        }
+        None => {}
+    }
+    // 既存のテストとの間に空行を入れる
+    if !source.trim().is_empty() {
+        table.decor_mut().set_prefix("\n");
+    }
+    tests.push(table);
+
+    Ok(document.to_string())
+}
+
+fn string_array(values: &[String]) -> toml_edit::Array {
+    values.iter().map(String::as_str).collect()
+}
+
+fn cargo_bin_fields(cargo_bin: &CargoBin) -> Vec<(&'static str, String)> {
+    let mut fields = Vec::new();
+    if let Some(package) = &cargo_bin.package {
+        fields.push(("package", package.clone()));
+    }
+    if let Some(bin) = &cargo_bin.bin {
+        fields.push(("bin", bin.clone()));
+    }
+    if let Some(path) = &cargo_bin.manifest_path {
+        fields.push(("manifest_path", path.display().to_string()));
+    }
+    fields
+}
+
+// YAML は書式を保ったまま編集できないので、tests の列の末尾に行を挿入する
+fn append_yaml_test(source: &str, test_case: &TestCase) -> Result<String> {
+    let mut lines: Vec<String> = source.lines().map(String::from).collect();
+
+    let tests_line = lines.iter().position(|line| {
+        line.strip_prefix("tests")
+            .is_some_and(|rest| rest.trim_start().starts_with(':'))
+    });
+    let (insert_at, item_indent, separate) = match tests_line {
+        Some(index) => {
+            let value = lines[index].split_once(':').map_or("", |(_, v)| v.trim());
+            match value {
+                "" => {}
+                // 空の列は書き足せるようにブロック形式にする
+                "[]" => lines[index] = "tests:".to_string(),
+                _ if value.starts_with('#') => {}
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "`tests` must be written as a block sequence to append to it"
+                    ))
+                }
+            }
+
+            // 列は次のトップレベルのキーの手前まで続く
+            let mut end = index + 1;
+            while end < lines.len() {
+                let line = &lines[end];
+                let trimmed = line.trim_start();
+                let top_level_key = !trimmed.is_empty()
+                    && !trimmed.starts_with('#')
+                    && line.len() == trimmed.len()
+                    && !trimmed.starts_with('-');
+                if top_level_key {
+                    break;
+                }
+                end += 1;
+            }
+            // 次のキーに付いたコメントや空行は残す
+            while end > index + 1 && {
+                let trimmed = lines[end - 1].trim_start();
+                trimmed.is_empty() || trimmed.starts_with('#')
+            } {
+                end -= 1;
+            }
+
+            let item_indent = lines[index + 1..end]
+                .iter()
+                .find(|line| line.trim_start().starts_with('-'))
+                .map(|line| line.len() - line.trim_start().len());
+            (end, item_indent.unwrap_or(2), item_indent.is_some())
+        }
+        None => {
+            while lines.last().is_some_and(|line| line.trim().is_empty()) {
+                lines.pop();
+            }
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.push("tests:".to_string());
+            (lines.len(), 2, false)
+        }
+    };
+
+    let mut item = Vec::new();
+    if separate {
+        item.push(String::new());
+    }
+    item.extend(yaml_test_lines(test_case, item_indent));
+    lines.splice(insert_at..insert_at, item);
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    Ok(text)
+}
+
+fn yaml_test_lines(test_case: &TestCase, item_indent: usize) -> Vec<String> {
+    let key_indent = item_indent + 2;
+    let key = |name: &str| format!("{}{}:", " ".repeat(key_indent), name);
+
+    let mut lines = bless::render_yaml_value(
+        &format!("{}- name:", " ".repeat(item_indent)),
+        key_indent,
+        &test_case.name,
+    );
+    match &test_case.cargo_bin {
+        Some(cargo_bin) => {
+            let fields: Vec<String> = cargo_bin_fields(cargo_bin)
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = serde_json::to_string(&value).expect("strings serialize to JSON");
+                    format!("{}: {}", key, value)
+                })
+                .collect();
+            lines.push(format!("{} {{ {} }}", key("cargo_bin"), fields.join(", ")));
+        }
+        None => lines.extend(bless::render_yaml_value(
+            &key("command"),
+            key_indent,
+            &test_case.command,
+        )),
+    }
+    if let Some(args) = &test_case.args {
+        // 既存の設定と同じくフロー形式で書く
+        let args: Vec<String> = args
+            .iter()
+            .map(|arg| serde_json::to_string(arg).expect("strings serialize to JSON"))
+            .collect();
+        lines.push(format!("{} [{}]", key("args"), args.join(", ")));
+    }
+    if let Some(input) = &test_case.input {
+        lines.extend(bless::render_yaml_value(&key("input"), key_indent, input));
+    }
+    if let Some(path) = &test_case.input_file {
+        lines.extend(bless::render_yaml_value(
+            &key("input_file"),
+            key_indent,
+            &path.display().to_string(),
+        ));
+    }
+    match (&test_case.expected_output_file, &test_case.expected_output) {
+        (Some(path), _) => lines.extend(bless::render_yaml_value(
+            &key("expected_output_file"),
+            key_indent,
+            &path.display().to_string(),
+        )),
+        (None, Some(output)) => lines.extend(bless::render_yaml_value(
+            &key("expected_output"),
+            key_indent,
+            output,
+        )),
+        (None, None) => {}
+    }
+    if let Some(timeout) = test_case.timeout_secs {
+        lines.push(format!("{} {}", key("timeout_secs"), timeout));
+    }
+    match &test_case.skip {
+        Some(Marker::Flag(flag)) => lines.push(format!("{} {}", key("skip"), flag)),
+        Some(Marker::Reason(reason)) => {
+            lines.extend(bless::render_yaml_value(&key("skip"), key_indent, reason))
+        }
+        None => {}
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::isolate::TempDir;
+    use crate::test;
+
+    fn recorded(name: &str, expected_output: &str) -> TestCase {
+        let mut test_case: TestCase = serde_yaml::from_str(&format!(
+            "{{ name: {:?}, command: \"echo\", args: [\"a: b\", \"#c\"], expected_output: \"\" }}",
+            name
+        ))
+        .expect("valid test case");
+        test_case.expected_output = Some(expected_output.to_string());
+        test_case
+    }
+
+    fn load(path: &Path) -> TestConfig {
+        test::load_config(&path.to_path_buf()).expect("recorded config loads")
+    }
+
+    #[test]
+    fn recorded_tests_load_back() {
+        let dir = TempDir::create("record", &[]).unwrap();
+        for file in ["new/tests.yaml", "new/tests.toml"] {
+            let path = dir.path().join(file);
+            append_test(&path, &recorded("first", "one line\n")).unwrap();
+            append_test(&path, &recorded("second: \"quoted\"", "two\n  lines\n")).unwrap();
+
+            let config = load(&path);
+            let names: Vec<&str> = config.tests.iter().map(|t| t.name.as_str()).collect();
+            assert_eq!(names, ["first", "second: \"quoted\""], "{}", file);
+            assert_eq!(
+                config.tests[1].expected_output.as_deref(),
+                Some("two\n  lines\n"),
+                "{}",
+                file
+            );
+            assert_eq!(
+                config.tests[1].args.as_deref(),
+                Some(&["a: b".to_string(), "#c".to_string()][..]),
+                "{}",
+                file
+            );
+        }
+    }
+
+    #[test]
+    fn recording_keeps_the_rest_of_the_yaml_file() {
+        let dir = TempDir::create("record yaml", &[]).unwrap();
+        let path = dir.path().join("tests.yaml");
+        let source = r#"# smoke tests
+tests:
+  - name: "existing"
+    command: "true"
+    expected_output: ""
+
+# runs after every test
+teardown: ["true"]
+"#;
+        std::fs::write(&path, source).unwrap();
+
+        append_test(&path, &recorded("recorded", "out\n")).unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.starts_with("# smoke tests\ntests:\n  - name: \"existing\"\n"));
+        assert!(written.ends_with("\n# runs after every test\nteardown: [\"true\"]\n"));
+        let config = load(&path);
+        assert_eq!(config.tests.len(), 2);
+        assert_eq!(config.tests[1].name, "recorded");
+        assert_eq!(config.teardown.as_deref(), Some(&["true".to_string()][..]));
+    }
+
+    #[test]
+    fn flow_sequences_of_tests_are_not_appended_to() {
+        let result = add_test(
+            Path::new("tests.yaml"),
+            "tests: [{ name: a, command: \"true\", expected_output: \"\" }]\n",
+            &recorded("b", ""),
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,325 @@
+// src/report/html.rs
+use crate::test::{DiffLine, RunOutcome, TestResult, TestStats, TestStatus};
+use similar::ChangeTag;
+use std::fmt::Write;
+
+/// Render the results as a single static HTML page (no external assets, no JavaScript)
+pub fn render(outcome: &RunOutcome) -> String {
+    let results = &outcome.results;
+    let stats = TestStats::from_results(results);
+    let slowest = results
+        .iter()
+        .map(|r| r.execution_time.as_secs_f64())
+        .fold(0.0, f64::max);
+
+    let mut html = String::new();
+    html.push_str(HEADER);
+    let _ = writeln!(
+        html,
+        "<h1>Yamori Test Report</h1>\n<p class=\"meta\">Generated {} by yamori {}</p>",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    write_summary(&mut html, &stats);
+    if let Some(reason) = &outcome.teardown_error {
+        let _ = writeln!(
+            html,
+            "<div class=\"suite-error\"><h3>Suite teardown failed</h3>\n<pre>{}</pre></div>",
+            escape(reason.trim_end_matches('\n'))
+        );
+    }
+
+    // テスト一覧の表
+    html.push_str("<h2>Tests</h2>\n<table class=\"tests\">\n");
+    html.push_str(
+        "<tr><th>#</th><th>Status</th><th>Name</th><th>Tags</th><th>Time</th><th>Reason</th></tr>\n",
+    );
+    for (i, result) in results.iter().enumerate() {
+        let seconds = result.execution_time.as_secs_f64();
+        let width = if slowest > 0.0 {
+            seconds / slowest * 100.0
+        } else {
+            0.0
+        };
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td><a href=\"#test-{}\">{}</a></td><td>{}</td>\
+             <td class=\"time\"><div class=\"bar\"><span style=\"width:{:.1}%\"></span></div>{}ms</td><td>{}</td></tr>",
+            i + 1,
+            badge(result.status),
+            i + 1,
+            escape(&result.name),
+            escape(&result.tags.join(", ")),
+            width,
+            result.execution_time.as_millis(),
+            escape(result.reason.as_deref().unwrap_or_default())
+        );
+    }
+    html.push_str("</table>\n");
+
+    // テストごとの詳細
+    html.push_str("<h2>Details</h2>\n");
+    for (i, result) in results.iter().enumerate() {
+        write_details(&mut html, i + 1, result);
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn write_summary(html: &mut String, stats: &TestStats) {
+    html.push_str("<div class=\"cards\">\n");
+    let _ = writeln!(
+        html,
+        "<div class=\"card\"><b>{:.1}%</b>pass rate ({}/{})</div>",
+        stats.pass_rate(),
+        stats.ok(),
+        stats.executed()
+    );
+    for (count, status) in status_counts(stats) {
+        if count > 0 || matches!(status, TestStatus::Passed | TestStatus::Failed) {
+            let _ = writeln!(
+                html,
+                "<div class=\"card {}\"><b>{}</b>{}</div>",
+                class(status),
+                count,
+                status.label().to_lowercase()
+            );
+        }
+    }
+    html.push_str("</div>\n");
+
+    // 状態ごとの割合を積み上げ棒で表示
+    if stats.total > 0 {
+        html.push_str("<div class=\"ratio\">");
+        for (count, status) in status_counts(stats) {
+            if count > 0 {
+                let _ = write!(
+                    html,
+                    "<span class=\"{}\" style=\"width:{:.2}%\" title=\"{} {}\"></span>",
+                    class(status),
+                    count as f64 / stats.total as f64 * 100.0,
+                    count,
+                    status.label()
+                );
+            }
+        }
+        html.push_str("</div>\n");
+    }
+}
+
+fn status_counts(stats: &TestStats) -> [(usize, TestStatus); 9] {
+    [
+        (stats.passed, TestStatus::Passed),
+        (stats.flaky, TestStatus::Flaky),
+        (stats.failed, TestStatus::Failed),
+        (stats.errors, TestStatus::Error),
+        (stats.hook_failures, TestStatus::HookFailed),
+        (stats.xpassed, TestStatus::XPassed),
+        (stats.xfailed, TestStatus::XFailed),
+        (stats.skipped, TestStatus::Skipped),
+        (stats.not_run, TestStatus::NotRun),
+    ]
+}
+
+fn write_details(html: &mut String, number: usize, result: &TestResult) {
+    // 失敗したテストは最初から開いておく
+    let _ = writeln!(
+        html,
+        "<details id=\"test-{}\"{}>\n<summary>{} {}. {} <span class=\"muted\">{}ms</span></summary>",
+        number,
+        if result.status.is_failure() { " open" } else { "" },
+        badge(result.status),
+        number,
+        escape(&result.name),
+        result.execution_time.as_millis()
+    );
+
+    html.push_str("<dl>\n");
+    let _ = writeln!(
+        html,
+        "<dt>Command</dt><dd><code>{} {}</code></dd>",
+        escape(&result.command),
+        escape(&result.args.join(" "))
+    );
+    let _ = writeln!(
+        html,
+        "<dt>Build mode</dt><dd>{}</dd>",
+        if result.is_release {
+            "release"
+        } else {
+            "debug"
+        }
+    );
+    if let Some(reason) = &result.reason {
+        let _ = writeln!(html, "<dt>Reason</dt><dd>{}</dd>", escape(reason));
+    }
+    if result.attempts.len() > 1 {
+        let attempts: Vec<String> = result
+            .attempts
+            .iter()
+            .map(|a| {
+                format!(
+                    "{} ({}ms{})",
+                    if a.success { "pass" } else { "fail" },
+                    a.execution_time.as_millis(),
+                    if a.timed_out { ", timed out" } else { "" }
+                )
+            })
+            .collect();
+        let _ = writeln!(
+            html,
+            "<dt>Attempts</dt><dd>{}</dd>",
+            escape(&attempts.join(" → "))
+        );
+    }
+    html.push_str("</dl>\n");
+
+    if let Some(input) = &result.input {
+        write_block(html, "Input", input);
+    }
+    if let Some(diff) = &result.diff {
+        html.push_str("<h4>Diff (expected / actual)</h4>\n");
+        write_side_by_side(html, diff);
+    }
+    for check in result.failed_checks() {
+        let _ = writeln!(html, "<h4>{}</h4>", escape(&check.summary()));
+        if let Some(diff) = check.diff {
+            write_side_by_side(html, diff);
+        }
+    }
+    if !result.attempts.is_empty() {
+        write_block(html, "Output", &result.actual_output);
+    }
+    if !result.stderr.is_empty() {
+        write_block(html, "Stderr", &result.stderr);
+    }
+    html.push_str("</details>\n");
+}
+
+fn write_block(html: &mut String, title: &str, content: &str) {
+    let _ = writeln!(
+        html,
+        "<h4>{}</h4>\n<pre>{}</pre>",
+        title,
+        escape(content.trim_end_matches('\n'))
+    );
+}
+
+/// Two-column diff: removed lines on the left are paired with added lines on the right
+fn write_side_by_side(html: &mut String, diff: &[DiffLine]) {
+    html.push_str("<table class=\"diff\">\n<tr><th>Expected</th><th>Actual</th></tr>\n");
+
+    let mut deleted: Vec<&str> = Vec::new();
+    let mut inserted: Vec<&str> = Vec::new();
+    for line in diff {
+        let content = line.content.trim_end_matches('\n');
+        match line.tag {
+            ChangeTag::Delete => deleted.push(content),
+            ChangeTag::Insert => inserted.push(content),
+            ChangeTag::Equal => {
+                flush_changes(html, &mut deleted, &mut inserted);
+                let _ = writeln!(html, "<tr><td>{0}</td><td>{0}</td></tr>", escape(content));
+            }
+        }
+    }
+    flush_changes(html, &mut deleted, &mut inserted);
+
+    html.push_str("</table>\n");
+}
+
+fn flush_changes(html: &mut String, deleted: &mut Vec<&str>, inserted: &mut Vec<&str>) {
+    for i in 0..deleted.len().max(inserted.len()) {
+        let cell = |line: Option<&&str>, class: &str| match line {
+            Some(line) => format!("<td class=\"{}\">{}</td>", class, escape(line)),
+            None => "<td class=\"empty\"></td>".to_string(),
+        };
+        let _ = writeln!(
+            html,
+            "<tr>{}{}</tr>",
+            cell(deleted.get(i), "del"),
+            cell(inserted.get(i), "ins")
+        );
+    }
+    deleted.clear();
+    inserted.clear();
+}
+
+fn badge(status: TestStatus) -> String {
+    format!(
+        "<span class=\"badge {}\">{}</span>",
+        class(status),
+        status.label()
+    )
+}
+
+fn class(status: TestStatus) -> &'static str {
+    match status {
+        TestStatus::Passed => "passed",
+        TestStatus::Failed => "failed",
+        TestStatus::Skipped => "skipped",
+        TestStatus::XFailed => "xfailed",
+        TestStatus::XPassed => "xpassed",
+        TestStatus::Flaky => "flaky",
+        TestStatus::NotRun => "not-run",
+        TestStatus::Error => "error",
+        TestStatus::HookFailed => "hook-failed",
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// 色は TUI の status_color に合わせる
+const HEADER: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Yamori Test Report</title>
+<style>
+body { font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; margin: 2em auto; max-width: 1100px; color: #222; padding: 0 1em; }
+h1 { margin-bottom: 0; }
+.meta, .muted { color: #777; font-size: 0.9em; }
+.cards { display: flex; flex-wrap: wrap; gap: 0.8em; margin: 1.5em 0 1em; }
+.card { border: 1px solid #ddd; border-radius: 6px; padding: 0.6em 1em; min-width: 6em; border-top: 4px solid #888; }
+.card b { display: block; font-size: 1.6em; }
+.ratio { display: flex; height: 12px; border-radius: 6px; overflow: hidden; background: #eee; }
+.ratio span { display: block; height: 100%; }
+table { border-collapse: collapse; width: 100%; }
+.tests th, .tests td { text-align: left; padding: 0.35em 0.6em; border-bottom: 1px solid #eee; vertical-align: top; }
+.time { white-space: nowrap; width: 12em; }
+.bar { display: inline-block; width: 6em; height: 8px; background: #eee; border-radius: 4px; margin-right: 0.5em; }
+.bar span { display: block; height: 100%; background: #4a90d9; border-radius: 4px; }
+.badge { display: inline-block; padding: 0.1em 0.5em; border-radius: 4px; color: #fff; font-size: 0.8em; font-weight: bold; }
+details { border: 1px solid #ddd; border-radius: 6px; margin: 0.6em 0; padding: 0.5em 1em; }
+summary { cursor: pointer; }
+dl { display: grid; grid-template-columns: 8em 1fr; gap: 0.2em 1em; }
+dt { color: #777; }
+dd { margin: 0; }
+pre, .diff td { font-family: Menlo, Consolas, monospace; font-size: 0.85em; }
+pre { background: #f6f8fa; padding: 0.6em; overflow-x: auto; }
+.diff { table-layout: fixed; }
+.diff th { text-align: left; width: 50%; }
+.diff td { white-space: pre-wrap; padding: 0 0.5em; border-right: 1px solid #ddd; }
+.diff td.del { background: #ffebe9; }
+.diff td.ins { background: #e6ffec; }
+.diff td.empty { background: #f6f8fa; }
+.passed { background: #2da44e; border-top-color: #2da44e; }
+.failed { background: #cf222e; border-top-color: #cf222e; }
+.error { background: #f85149; border-top-color: #f85149; }
+.hook-failed { background: #bc4c00; border-top-color: #bc4c00; }
+.skipped, .not-run { background: #8c959f; border-top-color: #8c959f; }
+.xfailed { background: #bf8700; border-top-color: #bf8700; }
+.xpassed { background: #8250df; border-top-color: #8250df; }
+.suite-error { border: 1px solid #bc4c00; border-left-width: 4px; border-radius: 6px; padding: 0 1em; margin: 1em 0; }
+.flaky { background: #1b9aaa; border-top-color: #1b9aaa; }
+.card.passed, .card.failed, .card.error, .card.hook-failed, .card.skipped, .card.not-run, .card.xfailed, .card.xpassed, .card.flaky { background: none; }
+</style>
+</head>
+<body>
+"#;
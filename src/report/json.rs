@@ -0,0 +1,122 @@
+// src/report/json.rs
+use super::ReportSpec;
+use crate::test::{RunOutcome, TestEvent, TestStats};
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    time::Instant,
+};
+
+/// Version of the JSON and JSONL schemas; bumped on incompatible changes
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Render the whole run as a single JSON document
+pub fn render(outcome: &RunOutcome) -> String {
+    let results = &outcome.results;
+    let duration_ms: f64 = results
+        .iter()
+        .map(|r| r.execution_time.as_secs_f64() * 1000.0)
+        .sum();
+
+    let report = json!({
+        "schema_version": SCHEMA_VERSION,
+        "tool": "yamori",
+        "version": env!("CARGO_PKG_VERSION"),
+        "generated_at": chrono::Local::now().to_rfc3339(),
+        "duration_ms": duration_ms,
+        "stats": TestStats::from_results(results),
+        "results": results,
+        "teardown_error": outcome.teardown_error,
+    });
+
+    let mut text = serde_json::to_string_pretty(&report).expect("results serialize to JSON");
+    text.push('\n');
+    text
+}
+
+/// JSONL event streams, written line by line while the tests run
+pub struct EventLog {
+    writers: Vec<(PathBuf, BufWriter<File>)>,
+    started: Instant,
+    /// 最初の書き込みエラー。イベント中には返せないので finish で返す
+    error: Option<anyhow::Error>,
+}
+
+impl EventLog {
+    /// Open every `jsonl` report and write the `run_started` event
+    pub fn open(reports: &[ReportSpec], total: usize) -> Result<Self> {
+        let mut writers = Vec::new();
+        for report in reports.iter().filter(|r| r.format.is_streamed()) {
+            super::create_parent_dir(&report.path)?;
+            let file = File::create(&report.path).with_context(|| {
+                format!("Failed to create jsonl report: {}", report.path.display())
+            })?;
+            writers.push((report.path.clone(), BufWriter::new(file)));
+        }
+
+        let mut log = EventLog {
+            writers,
+            started: Instant::now(),
+            error: None,
+        };
+        log.write(json!({
+            "event": "run_started",
+            "schema_version": SCHEMA_VERSION,
+            "tool": "yamori",
+            "version": env!("CARGO_PKG_VERSION"),
+            "timestamp": chrono::Local::now().to_rfc3339(),
+            "total": total,
+        }));
+        Ok(log)
+    }
+
+    pub fn record(&mut self, event: TestEvent) {
+        let line = match event {
+            TestEvent::Started { index, test } => json!({
+                "event": "test_started",
+                "index": index,
+                "name": test.name,
+            }),
+            TestEvent::Finished { index, result } => json!({
+                "event": "test_finished",
+                "index": index,
+                "result": result,
+            }),
+            TestEvent::BuildFinished { step } => json!({
+                "event": "build_finished",
+                "step": step,
+            }),
+        };
+        self.write(line);
+    }
+
+    /// Write the `run_finished` event and report the first write error, if any
+    pub fn finish(mut self, outcome: &RunOutcome) -> Result<()> {
+        self.write(json!({
+            "event": "run_finished",
+            "duration_ms": self.started.elapsed().as_secs_f64() * 1000.0,
+            "stats": TestStats::from_results(&outcome.results),
+            "teardown_error": outcome.teardown_error,
+        }));
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn write(&mut self, line: serde_json::Value) {
+        for (path, writer) in &mut self.writers {
+            // 途中経過を追えるように 1 行ごとにフラッシュする
+            let written = writeln!(writer, "{}", line).and_then(|_| writer.flush());
+            if let Err(e) = written {
+                self.error.get_or_insert_with(|| {
+                    anyhow::Error::new(e)
+                        .context(format!("Failed to write jsonl report: {}", path.display()))
+                });
+            }
+        }
+    }
+}
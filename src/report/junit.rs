@@ -0,0 +1,259 @@
+// src/report/junit.rs
+use crate::test::{DiffLine, RunOutcome, TestResult, TestStats, TestStatus};
+use similar::ChangeTag;
+use std::fmt::Write;
+
+/// Render the results as a JUnit XML document with one testcase per test
+pub fn render(outcome: &RunOutcome) -> String {
+    let results = &outcome.results;
+    let stats = TestStats::from_results(results);
+    let total_time: f64 = results.iter().map(|r| r.execution_time.as_secs_f64()).sum();
+    // xfail は期待どおりの失敗なので、JUnit では skipped として数える
+    let skipped = stats.skipped + stats.xfailed + stats.not_run;
+    let failures = stats.failed + stats.xpassed;
+    // フックの失敗はテスト本体の失敗ではないので error として数える
+    let errors = stats.errors + stats.hook_failures;
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"yamori\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.3}\">",
+        stats.total, failures, errors, skipped, total_time
+    );
+    let _ = writeln!(
+        xml,
+        "  <testsuite name=\"yamori\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.3}\" timestamp=\"{}\">",
+        stats.total,
+        failures,
+        errors,
+        skipped,
+        total_time,
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+
+    for result in results {
+        write_testcase(&mut xml, result);
+    }
+    // スイートの teardown はテストではないので、失敗はスイートの出力として残す
+    if let Some(reason) = &outcome.teardown_error {
+        let _ = writeln!(xml, "    <system-err>{}</system-err>", escape(reason));
+    }
+
+    xml.push_str("  </testsuite>\n");
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+fn write_testcase(xml: &mut String, result: &TestResult) {
+    let _ = writeln!(
+        xml,
+        "    <testcase name=\"{}\" classname=\"yamori\" time=\"{:.3}\">",
+        escape(&result.name),
+        result.execution_time.as_secs_f64()
+    );
+
+    let reason = result.reason.as_deref().unwrap_or_default();
+    match result.status {
+        TestStatus::Passed => {}
+        TestStatus::Failed => {
+            let kind = if result.attempts.last().is_some_and(|a| a.timed_out) {
+                "timeout"
+            } else {
+                "mismatch"
+            };
+            let message = if reason.is_empty() {
+                "output did not match the expected output"
+            } else {
+                reason
+            };
+            let _ = writeln!(
+                xml,
+                "      <failure message=\"{}\" type=\"{}\">{}</failure>",
+                escape(message),
+                kind,
+                escape(&failure_body(result))
+            );
+        }
+        TestStatus::XPassed => {
+            let _ = writeln!(
+                xml,
+                "      <failure message=\"{}\" type=\"xpass\">{}</failure>",
+                escape("marked as xfail but the output matched"),
+                escape(&failure_body(result))
+            );
+        }
+        TestStatus::Error => {
+            let _ = writeln!(
+                xml,
+                "      <error message=\"{}\" type=\"error\"/>",
+                escape(reason)
+            );
+        }
+        TestStatus::HookFailed => {
+            let _ = writeln!(
+                xml,
+                "      <error message=\"{}\" type=\"hook\"/>",
+                escape(reason)
+            );
+        }
+        TestStatus::Skipped | TestStatus::NotRun | TestStatus::XFailed => {
+            let message = match result.status {
+                TestStatus::XFailed if reason.is_empty() => "expected failure".to_string(),
+                TestStatus::XFailed => format!("expected failure: {}", reason),
+                TestStatus::NotRun if reason.is_empty() => "not run".to_string(),
+                _ => reason.to_string(),
+            };
+            let _ = writeln!(xml, "      <skipped message=\"{}\"/>", escape(&message));
+        }
+        TestStatus::Flaky => {
+            // 再試行で成功したテストは、失敗した試行を flakyFailure として残す
+            for (i, attempt) in result.attempts.iter().enumerate() {
+                if attempt.success {
+                    continue;
+                }
+                let _ = writeln!(
+                    xml,
+                    "      <flakyFailure message=\"attempt {} of {} failed\" type=\"{}\">{}",
+                    i + 1,
+                    result.attempts.len(),
+                    if attempt.timed_out {
+                        "timeout"
+                    } else {
+                        "mismatch"
+                    },
+                    escape(&failure_body(&result.with_attempt(i)))
+                );
+                write_output(xml, "        ", "system-out", &attempt.actual_output);
+                write_output(xml, "        ", "system-err", &attempt.stderr);
+                xml.push_str("      </flakyFailure>\n");
+            }
+        }
+    }
+
+    write_output(xml, "      ", "system-out", &result.actual_output);
+    write_output(xml, "      ", "system-err", &result.stderr);
+    xml.push_str("    </testcase>\n");
+}
+
+fn write_output(xml: &mut String, indent: &str, tag: &str, output: &str) {
+    if !output.is_empty() {
+        let _ = writeln!(xml, "{}<{}>{}</{}>", indent, tag, escape(output), tag);
+    }
+}
+
+/// The command and the diff between the expected and the actual output
+fn failure_body(result: &TestResult) -> String {
+    let mut body = format!("$ {} {}\n", result.command, result.args.join(" "));
+    if let Some(diff) = &result.diff {
+        body.push_str("--- expected\n+++ actual\n");
+        body.push_str(&render_diff(diff));
+    }
+    for check in result.failed_checks() {
+        let _ = writeln!(body, "{}", check.summary());
+        if let Some(diff) = check.diff {
+            let name = &check.name;
+            let _ = writeln!(body, "--- expected {}\n+++ actual {}", name, name);
+            body.push_str(&render_diff(diff));
+        }
+    }
+    body
+}
+
+fn render_diff(diff: &[DiffLine]) -> String {
+    let mut text = String::new();
+    for line in diff {
+        let sign = match line.tag {
+            ChangeTag::Delete => '-',
+            ChangeTag::Insert => '+',
+            ChangeTag::Equal => ' ',
+        };
+        let _ = writeln!(text, "{}{}", sign, line.content.trim_end_matches('\n'));
+    }
+    text
+}
+
+// XML の特殊文字をエスケープし、XML 1.0 で使えない制御文字を取り除く
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' || c == '\u{FFFE}' || c == '\u{FFFF}' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::isolate::TempDir;
+    use crate::test::{self, RunOptions, TestConfig};
+
+    fn run(yaml: &str) -> RunOutcome {
+        let config: TestConfig = serde_yaml::from_str(yaml).expect("valid test configuration");
+        test::run_tests(&config, &RunOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn escape_keeps_whitespace_and_drops_other_control_characters() {
+        assert_eq!(
+            escape("<a href=\"x\">'&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&apos;&amp;&apos;&lt;/a&gt;"
+        );
+        assert_eq!(escape("a\tb\r\nc\x1b[31md\x00e\u{FFFE}"), "a\tb\r\nc[31mde");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn output_with_control_characters_is_valid_xml() {
+        let xml = render(&run(r#"
+tests:
+  - name: "Colors <red>"
+    command: "printf"
+    args: ['\033[31mred\000\n']
+    expected_output: "plain"
+"#));
+
+        assert!(!xml.contains('\x1b'));
+        assert!(!xml.contains('\0'));
+        assert!(xml.contains("<testcase name=\"Colors &lt;red&gt;\""));
+        assert!(xml.contains("+[31mred"));
+        assert!(xml.contains("<system-out>[31mred\n</system-out>"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn flaky_test_keeps_its_failed_attempts() {
+        let dir = TempDir::create("junit flaky", &[]).unwrap();
+        let marker = dir.path().join("attempted");
+        let xml = render(&run(&format!(
+            r#"
+tests:
+  - name: "Flaky"
+    command: "sh"
+    args: ["-c", "if [ -e '{0}' ]; then echo ok; else touch '{0}'; echo bad; fi"]
+    expected_output: "ok"
+    retries: 1
+"#,
+            marker.display()
+        )));
+
+        assert!(xml.contains("failures=\"0\" errors=\"0\""));
+        assert!(!xml.contains("<failure "));
+        assert_eq!(xml.matches("<flakyFailure ").count(), 1);
+        assert!(xml
+            .contains("<flakyFailure message=\"attempt 1 of 2 failed\" type=\"mismatch\">$ sh -c"));
+        assert!(xml.contains("-ok\n+bad\n"));
+        // 失敗した試行の出力は flakyFailure の中に、最後の試行の出力はテストに残す
+        assert!(xml.contains("        <system-out>bad\n</system-out>\n      </flakyFailure>"));
+        assert!(xml.contains("      <system-out>ok\n</system-out>\n    </testcase>"));
+    }
+}
@@ -0,0 +1,122 @@
+// src/report/mod.rs
+mod html;
+mod json;
+mod junit;
+
+pub use json::EventLog;
+
+use crate::test::RunOutcome;
+use anyhow::{Context, Result};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// File formats a run can be written out as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// JUnit XML, as read by most CI dashboards
+    Junit,
+    /// The whole run as one JSON document
+    Json,
+    /// One JSON event per line, written while the tests run
+    Jsonl,
+    /// A self-contained HTML page to share with people who don't run yamori
+    Html,
+}
+
+impl ReportFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReportFormat::Junit => "junit",
+            ReportFormat::Json => "json",
+            ReportFormat::Jsonl => "jsonl",
+            ReportFormat::Html => "html",
+        }
+    }
+
+    /// Whether the report is written during the run by [`EventLog`]
+    pub fn is_streamed(&self) -> bool {
+        *self == ReportFormat::Jsonl
+    }
+
+    fn render(&self, outcome: &RunOutcome) -> Option<String> {
+        match self {
+            ReportFormat::Junit => Some(junit::render(outcome)),
+            ReportFormat::Json => Some(json::render(outcome)),
+            ReportFormat::Jsonl => None,
+            ReportFormat::Html => Some(html::render(outcome)),
+        }
+    }
+}
+
+/// A report requested on the command line, e.g. `junit=target/yamori.xml`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportSpec {
+    pub format: ReportFormat,
+    pub path: PathBuf,
+}
+
+impl FromStr for ReportSpec {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        let (format, path) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected FORMAT=PATH, got {:?}", value))?;
+
+        let format = match format.trim().to_lowercase().as_str() {
+            "junit" => ReportFormat::Junit,
+            "json" => ReportFormat::Json,
+            "jsonl" => ReportFormat::Jsonl,
+            "html" => ReportFormat::Html,
+            other => {
+                return Err(format!(
+                    "unknown report format {:?} (use junit, json, jsonl or html)",
+                    other
+                ))
+            }
+        };
+        if path.is_empty() {
+            return Err(format!("missing path for the {} report", format.name()));
+        }
+
+        Ok(ReportSpec {
+            format,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+impl fmt::Display for ReportSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.format.name(), self.path.display())
+    }
+}
+
+/// Write every requested report that is not streamed during the run
+pub fn write_reports(reports: &[ReportSpec], outcome: &RunOutcome) -> Result<()> {
+    for report in reports {
+        let Some(content) = report.format.render(outcome) else {
+            continue;
+        };
+        create_parent_dir(&report.path)?;
+        std::fs::write(&report.path, content).with_context(|| {
+            format!(
+                "Failed to write {} report: {}",
+                report.format.name(),
+                report.path.display()
+            )
+        })?;
+    }
+    Ok(())
+}
+
+fn create_parent_dir(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    Ok(())
+}
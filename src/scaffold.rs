@@ -0,0 +1,226 @@
+// src/scaffold.rs
+use crate::cargo::{Metadata, Package};
+use crate::record;
+use crate::test::{CargoBin, Marker, TestCase};
+use anyhow::Result;
+use std::path::{Component, Path, PathBuf};
+
+/// A starter configuration derived from the binary targets of a Cargo workspace
+#[derive(Debug, Clone)]
+pub struct Scaffold {
+    pub tests: Vec<TestCase>,
+    /// Number of binary targets found in the workspace
+    pub binaries: usize,
+    /// `*.in` / `*.out` files that could not be turned into a test
+    pub unmatched: Vec<PathBuf>,
+}
+
+/// A binary target and how the tests refer to it
+struct Binary<'a> {
+    name: &'a str,
+    package: &'a Package,
+    cargo_bin: CargoBin,
+}
+
+/// Inspect the workspace and build a configuration with one test per `*.in`/`*.out` pair
+///
+/// Binaries without such files get a skipped sample test to fill in.
+pub fn from_metadata(metadata: &Metadata, cwd: &Path, manifest_path: Option<&Path>) -> Scaffold {
+    let all_binaries: Vec<_> = metadata
+        .packages
+        .iter()
+        .flat_map(|package| package.binaries().map(move |bin| (package, bin)))
+        .collect();
+    let manifest_path = manifest_path.map(|path| relative_to(path, cwd));
+    let binaries: Vec<Binary> = all_binaries
+        .iter()
+        .map(|(package, bin)| {
+            // バイナリ名がワークスペース内で一意ならパッケージ名は書かない
+            let unique = all_binaries
+                .iter()
+                .filter(|(_, b)| b.name == bin.name)
+                .count()
+                == 1;
+            Binary {
+                name: &bin.name,
+                package,
+                cargo_bin: CargoBin {
+                    package: (!unique).then(|| package.name.clone()),
+                    bin: Some(bin.name.clone()),
+                    manifest_path: manifest_path.clone(),
+                },
+            }
+        })
+        .collect();
+
+    let mut fixtures = Vec::new();
+    collect_fixtures(
+        &metadata.workspace_root,
+        &metadata.target_directory,
+        &mut fixtures,
+    );
+    fixtures.sort();
+
+    let mut tests = Vec::new();
+    let mut unmatched = Vec::new();
+    for expected in fixtures.iter().filter(|f| has_extension(f, "out")) {
+        let Some(binary) = binary_for(expected, &binaries, metadata) else {
+            unmatched.push(relative_to(expected, cwd));
+            continue;
+        };
+        let input = expected.with_extension("in");
+        let label = expected
+            .strip_prefix(binary.package.root())
+            .or_else(|_| expected.strip_prefix(&metadata.workspace_root))
+            .unwrap_or(expected)
+            .with_extension("");
+        tests.push(TestCase {
+            input_file: input.exists().then(|| relative_to(&input, cwd)),
+            expected_output_file: Some(relative_to(expected, cwd)),
+            ..test_case(format!("{} - {}", binary.name, label.display()), binary)
+        });
+    }
+    // 対応する .out がない .in は入力だけでは期待値が決まらないので報告する
+    for input in fixtures.iter().filter(|f| has_extension(f, "in")) {
+        if !input.with_extension("out").exists() {
+            unmatched.push(relative_to(input, cwd));
+        }
+    }
+
+    for binary in &binaries {
+        let prefix = format!("{} - ", binary.name);
+        if !tests.iter().any(|t| t.name.starts_with(&prefix)) {
+            tests.push(TestCase {
+                skip: Some(Marker::Reason(
+                    "fill in input and expected_output, then remove skip".to_string(),
+                )),
+                expected_output: Some(String::new()),
+                ..test_case(format!("{}sample", prefix), binary)
+            });
+        }
+    }
+
+    Scaffold {
+        tests,
+        binaries: binaries.len(),
+        unmatched,
+    }
+}
+
+/// The configuration file text, in the format implied by the extension of `config_path`
+pub fn render(config_path: &Path, scaffold: &Scaffold) -> Result<String> {
+    let extension = config_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+
+    let mut source = match extension.as_str() {
+        "toml" => "# yamori tests configuration, generated from the Cargo workspace\n\n\
+                   # Global build configuration (cargo_bin binaries are built in this mode)\n\
+                   [build]\n\
+                   release = false\n"
+            .to_string(),
+        _ => "# yamori tests configuration, generated from the Cargo workspace\n\n\
+              # Global build configuration (cargo_bin binaries are built in this mode)\n\
+              build:\n  release: false\n"
+            .to_string(),
+    };
+
+    for test_case in &scaffold.tests {
+        source = record::add_test(config_path, &source, test_case)?;
+    }
+    Ok(source)
+}
+
+fn test_case(name: String, binary: &Binary) -> TestCase {
+    TestCase {
+        name,
+        command: String::new(),
+        cargo_bin: Some(binary.cargo_bin.clone()),
+        args: None,
+        input: None,
+        input_file: None,
+        expected_output: None,
+        expected_output_file: None,
+        timeout_secs: Some(5),
+        build: None,
+        tags: None,
+        skip: None,
+        only: None,
+        xfail: None,
+        retries: None,
+        needs: None,
+        setup: None,
+        teardown: None,
+        isolate: None,
+        fixtures: None,
+        expected_files: None,
+        pty: None,
+        screen: None,
+        dialog: None,
+    }
+}
+
+// 入出力ファイルのパスに含まれるディレクトリ名、入っているパッケージの順にバイナリを探す
+fn binary_for<'a>(
+    fixture: &Path,
+    binaries: &'a [Binary<'a>],
+    metadata: &Metadata,
+) -> Option<&'a Binary<'a>> {
+    let relative = fixture.strip_prefix(&metadata.workspace_root).ok()?;
+    let by_directory = relative.parent()?.components().rev().find_map(|c| match c {
+        Component::Normal(name) => binaries.iter().find(|b| name == b.name),
+        _ => None,
+    });
+    if by_directory.is_some() {
+        return by_directory;
+    }
+
+    let package = metadata
+        .packages
+        .iter()
+        .filter(|p| fixture.starts_with(p.root()))
+        .max_by_key(|p| p.root().components().count());
+    let candidates: Vec<&Binary> = match package {
+        Some(package) => binaries
+            .iter()
+            .filter(|b| b.package.manifest_path == package.manifest_path)
+            .collect(),
+        None => binaries.iter().collect(),
+    };
+    match candidates.as_slice() {
+        [only] => Some(only),
+        _ => None,
+    }
+}
+
+fn collect_fixtures(dir: &Path, target_dir: &Path, fixtures: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if path.is_dir() {
+            // ビルド成果物と隠しディレクトリは探さない
+            if !hidden && path != target_dir && path.file_name() != Some("target".as_ref()) {
+                collect_fixtures(&path, target_dir, fixtures);
+            }
+        } else if !hidden && (has_extension(&path, "in") || has_extension(&path, "out")) {
+            fixtures.push(path);
+        }
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().is_some_and(|ext| ext == extension)
+}
+
+fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    match path.strip_prefix(base) {
+        Ok(relative) if relative.as_os_str().is_empty() => PathBuf::from("."),
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => path.to_path_buf(),
+    }
+}
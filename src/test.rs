@@ -1,56 +1,637 @@
+use crate::build::{self, BuildStep, StepKind};
+use crate::cache::BuildCache;
+use crate::cargo;
+use crate::error::ErrorKind;
+use crate::graph::{self, BuildGraph};
+use crate::isolate::{self, TempDir};
+use crate::pty;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use similar::{ChangeTag, TextDiff};
 use std::{
-    io::Write,
-    path::PathBuf,
+    collections::BTreeMap,
+    io::{Read, Write},
+    path::{Path, PathBuf},
     process::{Command, Stdio},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TestConfig {
     pub tests: Vec<TestCase>,
     pub build: Option<BuildConfig>,
+    /// Default number of retries for failing tests
+    pub retries: Option<u32>,
+    /// Globs of source files that trigger a rerun in watch mode
+    pub watch: Option<Vec<String>>,
+    /// Commands run once before the first test
+    pub setup: Option<Vec<String>>,
+    /// Commands run once after the last test, even if tests failed or the run stopped early
+    pub teardown: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BuildConfig {
     pub release: bool,
-    pub pre_build_commands: Option<Vec<String>>,
+    pub pre_build_commands: Option<Vec<PreBuildCommand>>,
+    /// Named build steps, run in parallel in the order of their dependencies (global only)
+    pub steps: Option<Vec<NamedStep>>,
+    /// How many build steps run at the same time (default: the number of CPUs)
+    pub jobs: Option<usize>,
+}
+
+/// A build step of `build.steps`, which tests refer to with `needs`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NamedStep {
+    pub name: String,
+    pub command: String,
+    /// Steps that must succeed before this one starts
+    pub depends_on: Option<Vec<String>>,
+    /// Globs of the files the command reads, as for pre-build commands
+    pub inputs: Option<Vec<String>>,
+    pub outputs: Option<Vec<PathBuf>>,
+}
+
+/// A pre-build command, written as a string or as a table with the files it reads and writes
+///
+/// A command with `inputs` is skipped while those files are unchanged since its last success.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum PreBuildCommand {
+    Shell(String),
+    Step {
+        command: String,
+        /// Globs of the files the command reads
+        inputs: Option<Vec<String>>,
+        /// Files the command creates; the command runs again if one of them is missing
+        outputs: Option<Vec<PathBuf>>,
+    },
+}
+
+impl PreBuildCommand {
+    pub fn command(&self) -> &str {
+        match self {
+            PreBuildCommand::Shell(command) | PreBuildCommand::Step { command, .. } => command,
+        }
+    }
+
+    pub fn inputs(&self) -> &[String] {
+        match self {
+            PreBuildCommand::Shell(_) => &[],
+            PreBuildCommand::Step { inputs, .. } => inputs.as_deref().unwrap_or_default(),
+        }
+    }
+
+    pub fn outputs(&self) -> &[PathBuf] {
+        match self {
+            PreBuildCommand::Shell(_) => &[],
+            PreBuildCommand::Step { outputs, .. } => outputs.as_deref().unwrap_or_default(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TestCase {
     pub name: String,
+    /// Program to run; not needed when `cargo_bin` is set
+    #[serde(default)]
     pub command: String,
+    /// Run a binary target of a Cargo workspace instead of `command`
+    pub cargo_bin: Option<CargoBin>,
     pub args: Option<Vec<String>>,
     pub input: Option<String>,
-    pub expected_output: String,
+    /// Read the input from this file instead of `input`
+    pub input_file: Option<PathBuf>,
+    pub expected_output: Option<String>,
+    /// Read the expected output from this file instead of `expected_output`
+    pub expected_output_file: Option<PathBuf>,
     pub timeout_secs: Option<u64>,
     pub build: Option<BuildConfig>,
+    pub tags: Option<Vec<String>>,
+    pub skip: Option<Marker>,
+    pub only: Option<bool>,
+    pub xfail: Option<Marker>,
+    pub retries: Option<u32>,
+    /// Build steps to wait for; without it, the test waits for all of them
+    pub needs: Option<Vec<String>>,
+    /// Commands run before the test
+    pub setup: Option<Vec<String>>,
+    /// Commands run after the test, whatever its outcome
+    pub teardown: Option<Vec<String>>,
+    /// Run the test and its hooks in a fresh temporary directory
+    pub isolate: Option<bool>,
+    /// Files and directories copied into the temporary directory of an isolated test
+    pub fixtures: Option<Vec<PathBuf>>,
+    /// Files the command must create, checked after each run (paths relative to where it runs)
+    pub expected_files: Option<BTreeMap<PathBuf, ExpectedFile>>,
+    /// Run the command in a pseudo-terminal instead of with pipes
+    pub pty: Option<PtyOption>,
+    /// Drive a terminal program with keystrokes and compare its screen
+    pub screen: Option<ScreenConfig>,
+    /// Wait for prompts and answer them, like `expect`
+    pub dialog: Option<Vec<DialogStep>>,
 }
 
-#[derive(Debug, Clone)]
+/// What a file of `expected_files` must contain
+///
+/// A string is the expected content; a table is a rule with exactly one of its fields set.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ExpectedFile {
+    Content(String),
+    Rule(FileRule),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FileRule {
+    /// Read the expected content from this file (relative to the working directory)
+    pub file: Option<PathBuf>,
+    /// Whether the file must exist, whatever its content
+    pub exists: Option<bool>,
+    /// Whether the file must not exist
+    pub absent: Option<bool>,
+    /// A regular expression the content must match
+    pub regex: Option<String>,
+}
+
+impl FileRule {
+    fn count(&self) -> usize {
+        [
+            self.file.is_some(),
+            self.exists.is_some(),
+            self.absent.is_some(),
+            self.regex.is_some(),
+        ]
+        .iter()
+        .filter(|set| **set)
+        .count()
+    }
+}
+
+/// A binary target of a Cargo workspace, built for the build mode of the test before it runs
+///
+/// `package` and `bin` can be left out when the workspace has a single package or binary.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct CargoBin {
+    pub package: Option<String>,
+    pub bin: Option<String>,
+    /// Cargo.toml of the workspace (defaults to the one of the working directory)
+    pub manifest_path: Option<PathBuf>,
+}
+
+impl CargoBin {
+    /// Short description used where the resolved path is not known yet
+    pub fn label(&self) -> String {
+        let target = match (&self.package, &self.bin) {
+            (Some(package), Some(bin)) if package != bin => format!("{}/{}", package, bin),
+            (_, Some(name)) | (Some(name), None) => name.clone(),
+            (None, None) => return "cargo_bin".to_string(),
+        };
+        format!("cargo_bin:{}", target)
+    }
+}
+
+/// The `pty` option of a test, written as a boolean or as a table of settings
+///
+/// `pty: true` runs the command in an 80x24 terminal; `pty: { cols: 120 }` changes its size.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum PtyOption {
+    Flag(bool),
+    Settings(PtySettings),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PtySettings {
+    pub rows: Option<u16>,
+    pub cols: Option<u16>,
+    /// Remove ANSI escape sequences (colors, cursor movement) before comparing
+    pub strip_ansi: Option<bool>,
+}
+
+impl PtyOption {
+    /// The settings, or `None` when the command runs with pipes
+    pub fn settings(&self) -> Option<PtySettings> {
+        match self {
+            PtyOption::Flag(true) => Some(PtySettings::default()),
+            PtyOption::Flag(false) => None,
+            PtyOption::Settings(settings) => Some(settings.clone()),
+        }
+    }
+}
+
+impl PtySettings {
+    pub fn size(&self) -> (u16, u16) {
+        (
+            self.rows.unwrap_or(pty::DEFAULT_ROWS),
+            self.cols.unwrap_or(pty::DEFAULT_COLS),
+        )
+    }
+}
+
+/// Keystrokes typed into a terminal program and the screens it must show
+///
+/// The command runs in a pseudo-terminal whose output is rendered as a VT100 screen.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ScreenConfig {
+    /// Steps run in order once the command started
+    pub keys: Option<Vec<ScreenStep>>,
+    /// The screen once the command exited
+    pub expected: Option<String>,
+    /// Screens expected at the `snapshot` steps, by name
+    pub snapshots: Option<BTreeMap<String, String>>,
+}
+
+/// One step of a screen script: send text or a key, wait, then take a snapshot
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ScreenStep {
+    /// Text typed into the terminal
+    pub send: Option<String>,
+    /// A named key such as `enter`, `up` or `ctrl-c`
+    pub key: Option<String>,
+    /// How long to wait afterwards, in milliseconds
+    pub wait_ms: Option<u64>,
+    /// Take a snapshot of the screen under this name
+    pub snapshot: Option<String>,
+}
+
+/// A test marker written either as a boolean or as a reason string
+///
+/// `skip: true` and `skip: "flaky on CI"` both mark the test as skipped.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Marker {
+    Flag(bool),
+    Reason(String),
+}
+
+impl Marker {
+    pub fn is_set(&self) -> bool {
+        match self {
+            Marker::Flag(flag) => *flag,
+            Marker::Reason(_) => true,
+        }
+    }
+
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            Marker::Flag(_) => None,
+            Marker::Reason(reason) => Some(reason),
+        }
+    }
+}
+
+impl TestCase {
+    pub fn is_skipped(&self) -> bool {
+        self.skip.as_ref().is_some_and(Marker::is_set)
+    }
+
+    pub fn is_xfail(&self) -> bool {
+        self.xfail.as_ref().is_some_and(Marker::is_set)
+    }
+
+    pub fn is_only(&self) -> bool {
+        self.only.unwrap_or(false)
+    }
+
+    pub fn is_isolated(&self) -> bool {
+        self.isolate.unwrap_or(false)
+    }
+
+    /// Whether the test checks anything: its output, screen, dialog or files
+    pub fn has_expectation(&self) -> bool {
+        self.expected_output.is_some()
+            || self.expected_output_file.is_some()
+            || self.screen.is_some()
+            || self.dialog.as_ref().is_some_and(|steps| !steps.is_empty())
+            || self
+                .expected_files
+                .as_ref()
+                .is_some_and(|files| !files.is_empty())
+    }
+
+    /// `command`, or a description of `cargo_bin`
+    pub fn command_label(&self) -> String {
+        match &self.cargo_bin {
+            Some(cargo_bin) => cargo_bin.label(),
+            None => self.command.clone(),
+        }
+    }
+}
+
+/// Final verdict of a test, taking the skip and xfail markers into account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Skipped,
+    /// Marked `xfail` and failed as expected
+    #[serde(rename = "xfailed")]
+    XFailed,
+    /// Marked `xfail` but passed; treated as a failure so the marker gets removed
+    #[serde(rename = "xpassed")]
+    XPassed,
+    /// Failed at first but passed on a retry
+    Flaky,
+    /// Not started because the run stopped early (fail-fast or time budget)
+    NotRun,
+    /// Could not be run, e.g. the command could not be spawned or its build failed
+    Error,
+    /// A setup or teardown command failed
+    HookFailed,
+}
+
+impl TestStatus {
+    /// Whether this status makes the whole run fail
+    pub fn is_failure(self) -> bool {
+        matches!(
+            self,
+            TestStatus::Failed | TestStatus::XPassed | TestStatus::Error | TestStatus::HookFailed
+        )
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TestStatus::Passed => "PASS",
+            TestStatus::Failed => "FAIL",
+            TestStatus::Skipped => "SKIP",
+            TestStatus::XFailed => "XFAIL",
+            TestStatus::XPassed => "XPASS",
+            TestStatus::Flaky => "FLAKY",
+            TestStatus::NotRun => "NOT RUN",
+            TestStatus::Error => "ERROR",
+            TestStatus::HookFailed => "HOOK",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct TestResult {
     pub name: String,
+    /// Whether the actual output matched the expected output
     pub success: bool,
+    pub status: TestStatus,
+    /// Skip or xfail reason, error message, or why the test was not run
+    pub reason: Option<String>,
     pub actual_output: String,
+    pub stderr: String,
     pub diff: Option<Vec<DiffLine>>,
     pub command: String,
     pub args: Vec<String>,
     pub input: Option<String>,
+    #[serde(rename = "duration_ms", serialize_with = "serialize_millis")]
     pub execution_time: Duration,
     pub is_release: bool,
     pub build_commands: Option<Vec<String>>,
+    pub tags: Vec<String>,
+    /// Every execution of the test, including retries; the last one is the verdict
+    pub attempts: Vec<TestAttempt>,
+    /// Checks of the `expected_files` in the last attempt
+    pub file_checks: Vec<FileCheck>,
+    /// Checks of the screen snapshots in the last attempt
+    pub snapshot_checks: Vec<SnapshotCheck>,
+    /// The steps of the dialog in the last attempt, up to the first that failed
+    pub dialog: Vec<DialogEvent>,
+    /// Temporary directory of an isolated test, kept after a failure with `--keep-temp`
+    pub temp_dir: Option<PathBuf>,
+}
+
+/// One execution of a test command
+#[derive(Debug, Clone, Serialize)]
+pub struct TestAttempt {
+    pub success: bool,
+    pub actual_output: String,
+    pub stderr: String,
+    pub diff: Option<Vec<DiffLine>>,
+    #[serde(rename = "duration_ms", serialize_with = "serialize_millis")]
+    pub execution_time: Duration,
+    pub timed_out: bool,
+    pub file_checks: Vec<FileCheck>,
+    pub snapshot_checks: Vec<SnapshotCheck>,
+    pub dialog: Vec<DialogEvent>,
 }
 
+/// Check of one file of `expected_files`
+#[derive(Debug, Clone, Serialize)]
+pub struct FileCheck {
+    pub path: PathBuf,
+    pub success: bool,
+    /// Why the check failed, unless the content differs
+    pub message: Option<String>,
+    /// Diff between the expected and the actual content, when the content differs
+    pub diff: Option<Vec<DiffLine>>,
+}
+
+/// Check of one named screen snapshot
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotCheck {
+    pub name: String,
+    pub success: bool,
+    /// Why the check failed, unless the screen differs
+    pub message: Option<String>,
+    /// Diff between the expected and the actual screen, when the screen differs
+    pub diff: Option<Vec<DiffLine>>,
+}
+
+/// One step of a dialog as it ran
+#[derive(Debug, Clone, Serialize)]
+pub struct DialogEvent {
+    pub expect: Option<String>,
+    pub send: Option<String>,
+    /// Whether `expect` matched in time (always true without `expect`)
+    pub success: bool,
+    /// The output read while waiting for `expect`, from the previous match to this one
+    pub output: String,
+    /// Why the step failed
+    pub message: Option<String>,
+}
+
+/// A failed check of an expected file, a screen snapshot or a dialog step, as shown in the
+/// outputs
 #[derive(Debug, Clone)]
+pub struct FailedCheck<'a> {
+    /// `File`, `Snapshot` or `Dialog`
+    pub kind: &'static str,
+    pub name: String,
+    pub message: &'a str,
+    pub diff: Option<&'a [DiffLine]>,
+}
+
+impl FailedCheck<'_> {
+    /// One line describing the failure, e.g. `File out.txt: content differs`
+    pub fn summary(&self) -> String {
+        format!("{} {}: {}", self.kind, self.name, self.message)
+    }
+}
+
+impl TestResult {
+    /// The checks of `expected_files`, of the screen snapshots and of the dialog that failed
+    pub fn failed_checks(&self) -> Vec<FailedCheck<'_>> {
+        let files = self
+            .file_checks
+            .iter()
+            .filter(|check| !check.success)
+            .map(|check| FailedCheck {
+                kind: "File",
+                name: check.path.display().to_string(),
+                message: check.message.as_deref().unwrap_or("content differs"),
+                diff: check.diff.as_deref(),
+            });
+        let snapshots = self
+            .snapshot_checks
+            .iter()
+            .filter(|check| !check.success)
+            .map(|check| FailedCheck {
+                kind: "Snapshot",
+                name: check.name.clone(),
+                message: check.message.as_deref().unwrap_or("screen differs"),
+                diff: check.diff.as_deref(),
+            });
+        let dialog = self
+            .dialog
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| !event.success)
+            .map(|(i, event)| FailedCheck {
+                kind: "Dialog",
+                name: format!("step #{}", i + 1),
+                message: event.message.as_deref().unwrap_or("no match"),
+                diff: None,
+            });
+        files.chain(snapshots).chain(dialog).collect()
+    }
+
+    /// A copy of this result showing the given attempt instead of the last one
+    pub fn with_attempt(&self, index: usize) -> TestResult {
+        let mut result = self.clone();
+        if let Some(attempt) = self.attempts.get(index) {
+            result.success = attempt.success;
+            result.actual_output = attempt.actual_output.clone();
+            result.stderr = attempt.stderr.clone();
+            result.diff = attempt.diff.clone();
+            result.execution_time = attempt.execution_time;
+            result.file_checks = attempt.file_checks.clone();
+            result.snapshot_checks = attempt.snapshot_checks.clone();
+            result.dialog = attempt.dialog.clone();
+        }
+        result
+    }
+}
+
+/// Number of results per status
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct TestStats {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub xfailed: usize,
+    pub xpassed: usize,
+    pub flaky: usize,
+    pub not_run: usize,
+    pub errors: usize,
+    pub hook_failures: usize,
+}
+
+impl TestStats {
+    pub fn from_results(results: &[TestResult]) -> Self {
+        let mut stats = TestStats {
+            total: results.len(),
+            ..Default::default()
+        };
+        for result in results {
+            match result.status {
+                TestStatus::Passed => stats.passed += 1,
+                TestStatus::Failed => stats.failed += 1,
+                TestStatus::Skipped => stats.skipped += 1,
+                TestStatus::XFailed => stats.xfailed += 1,
+                TestStatus::XPassed => stats.xpassed += 1,
+                TestStatus::Flaky => stats.flaky += 1,
+                TestStatus::NotRun => stats.not_run += 1,
+                TestStatus::Error => stats.errors += 1,
+                TestStatus::HookFailed => stats.hook_failures += 1,
+            }
+        }
+        stats
+    }
+
+    /// Tests that were actually executed
+    pub fn executed(&self) -> usize {
+        self.total - self.skipped - self.not_run
+    }
+
+    /// Executed tests that did not fail the run
+    pub fn ok(&self) -> usize {
+        self.executed() - self.failed - self.xpassed - self.errors - self.hook_failures
+    }
+
+    pub fn pass_rate(&self) -> f64 {
+        if self.executed() > 0 {
+            (self.ok() as f64 / self.executed() as f64) * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.failed + self.xpassed + self.errors + self.hook_failures > 0
+    }
+
+    /// One-line breakdown such as `3 passed, 1 failed, 2 skipped`
+    pub fn summary(&self) -> String {
+        let mut parts = vec![
+            format!("{} passed", self.passed),
+            format!("{} failed", self.failed),
+        ];
+        for (count, label) in [
+            (self.skipped, "skipped"),
+            (self.xfailed, "xfailed"),
+            (self.xpassed, "xpassed"),
+            (self.flaky, "flaky"),
+            (self.errors, "errored"),
+            (self.hook_failures, "hook failed"),
+            (self.not_run, "not run"),
+        ] {
+            if count > 0 {
+                parts.push(format!("{} {}", count, label));
+            }
+        }
+        parts.join(", ")
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct DiffLine {
+    #[serde(serialize_with = "serialize_change_tag")]
     pub tag: ChangeTag,
     pub content: String,
 }
 
+// ChangeTag は Serialize を実装していないので、"equal" / "delete" / "insert" として書き出す
+fn serialize_change_tag<S: serde::Serializer>(
+    tag: &ChangeTag,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(match tag {
+        ChangeTag::Equal => "equal",
+        ChangeTag::Delete => "delete",
+        ChangeTag::Insert => "insert",
+    })
+}
+
+/// Durations are written as (fractional) milliseconds
+fn serialize_millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
 pub fn load_config(config_path: &PathBuf) -> Result<TestConfig> {
     // Check if file exists
     if !config_path.exists() {
@@ -72,22 +653,168 @@ pub fn load_config(config_path: &PathBuf) -> Result<TestConfig> {
         .unwrap_or_default();
 
     // 拡張子に応じて適切なパーサーを使用
-    match extension.as_str() {
+    let mut config: TestConfig = match extension.as_str() {
         "yaml" | "yml" => {
             // コマンド出力を抑制
             // println!("Parsing YAML configuration from {}", config_path.display());
-            serde_yaml::from_str(&content).map_err(|e| anyhow::anyhow!("YAML parse error: {}", e))
+            serde_yaml::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("YAML parse error: {}", e))?
         }
         "toml" => {
             // コマンド出力を抑制
             // println!("Parsing TOML configuration from {}", config_path.display());
-            toml::from_str(&content).map_err(|e| anyhow::anyhow!("TOML parse error: {}", e))
+            toml::from_str(&content).map_err(|e| anyhow::anyhow!("TOML parse error: {}", e))?
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Unsupported configuration format: {}. Please use .yaml, .yml, or .toml files.",
+                extension
+            ))
         }
-        _ => Err(anyhow::anyhow!(
-            "Unsupported configuration format: {}. Please use .yaml, .yml, or .toml files.",
-            extension
-        )),
+    };
+
+    for test in &mut config.tests {
+        // expected_output を書き忘れたり綴りを誤ったりしたテストを、空の出力を期待するテストにしない
+        if !test.has_expectation() {
+            return Err(anyhow::anyhow!(
+                "Test '{}' has nothing to check; {}",
+                test.name,
+                MISSING_EXPECTATION
+            ));
+        }
+        load_fixture_files(test)?;
     }
+
+    Ok(config)
+}
+
+const MISSING_EXPECTATION: &str =
+    "set expected_output, expected_output_file, screen, dialog or expected_files";
+
+// input_file / expected_output_file の内容を読み込む（パスは作業ディレクトリからの相対パス）
+fn load_fixture_files(test: &mut TestCase) -> Result<()> {
+    if let Some(path) = &test.input_file {
+        if test.input.is_some() {
+            return Err(anyhow::anyhow!(
+                "Test '{}' has both `input` and `input_file`",
+                test.name
+            ));
+        }
+        test.input = Some(std::fs::read_to_string(path).with_context(|| {
+            format!(
+                "Failed to read input file of test '{}': {}",
+                test.name,
+                path.display()
+            )
+        })?);
+    }
+
+    if let Some(path) = &test.expected_output_file {
+        if test.expected_output.is_some() {
+            return Err(anyhow::anyhow!(
+                "Test '{}' has both `expected_output` and `expected_output_file`",
+                test.name
+            ));
+        }
+        test.expected_output = Some(std::fs::read_to_string(path).with_context(|| {
+            format!(
+                "Failed to read expected output file of test '{}': {}",
+                test.name,
+                path.display()
+            )
+        })?);
+    }
+
+    Ok(())
+}
+
+/// Files a test reads besides the configuration (`input_file`, `expected_output_file`,
+/// `fixtures` and the `file` references of `expected_files`)
+pub fn fixture_files(test: &TestCase) -> Vec<&PathBuf> {
+    let references =
+        test.expected_files
+            .iter()
+            .flatten()
+            .filter_map(|(_, expected)| match expected {
+                ExpectedFile::Rule(rule) => rule.file.as_ref(),
+                ExpectedFile::Content(_) => None,
+            });
+    test.input_file
+        .iter()
+        .chain(test.expected_output_file.iter())
+        .chain(test.fixtures.iter().flatten())
+        .chain(references)
+        .collect()
+}
+
+/// 1-based line of the `name` key of a test in the source of a YAML or TOML config
+///
+/// Only `name: ...` / `name = ...` on a line of its own is recognized, and only in the entries
+/// of `tests` (a build step with the same name is not a test).
+pub fn find_test_line(source: &str, name: &str) -> Option<usize> {
+    // どこを読んでいるか（YAML のテストのキーが始まる列は最初の項目で決まる）
+    enum Section {
+        Other,
+        TomlTest,
+        YamlTests(Option<usize>),
+    }
+
+    let name = regex::escape(name);
+    let pattern = format!(
+        r#"^\s*(?:-\s*)?name\s*[:=]\s*(?:"{0}"|'{0}'|{0})\s*(?:#.*)?$"#,
+        name
+    );
+    let regex = regex::Regex::new(&pattern).ok()?;
+
+    let mut section = Section::Other;
+    for (i, line) in source.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        // TOML の表の見出しと YAML のトップレベルのキーで区切る
+        if trimmed.len() == line.len() {
+            if trimmed.starts_with('[') {
+                section = if trimmed.starts_with("[[tests]]") {
+                    Section::TomlTest
+                } else {
+                    Section::Other
+                };
+                continue;
+            }
+            let yaml_key = trimmed.split_once(':').map(|(key, _)| key).filter(|key| {
+                !key.is_empty()
+                    && key
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            });
+            if let Some(key) = yaml_key {
+                section = if key == "tests" {
+                    Section::YamlTests(None)
+                } else {
+                    Section::Other
+                };
+                continue;
+            }
+        }
+
+        let is_test_name = match &mut section {
+            Section::Other => false,
+            Section::TomlTest => true,
+            // 項目の中の入れ子のキーは、テストのキーより深い列から始まる
+            Section::YamlTests(_) if trimmed == "-" => continue,
+            Section::YamlTests(key_column) => {
+                let key = trimmed.strip_prefix('-').map_or(trimmed, str::trim_start);
+                let column = line.len() - key.len();
+                *key_column.get_or_insert(column) == column
+            }
+        };
+        if is_test_name && regex.is_match(line) {
+            return Some(i + 1);
+        }
+    }
+    None
 }
 
 // テンプレート変数を処理する関数
@@ -179,31 +906,290 @@ fn process_template(template: &str, is_release: bool) -> String {
     }
 }
 
-// ビルド前のコマンドを実行する関数
-pub fn run_pre_build_commands(config: &TestConfig) -> Result<()> {
+/// Resolve the template variables in a test's arguments for the given build mode
+pub fn resolve_args(test: &TestCase, is_release: bool) -> Vec<String> {
+    test.args
+        .as_ref()
+        .map(|args| {
+            args.iter()
+                .map(|arg| process_template(arg, is_release))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Whether a test runs in release mode (per-test build settings win over the global ones)
+pub fn is_release_for(config: &TestConfig, test: &TestCase) -> bool {
+    let global_release = config.build.as_ref().is_some_and(|b| b.release);
+    test.build.as_ref().map_or(global_release, |b| b.release)
+}
+
+/// One step of a dialog: wait for the output to match `expect`, then type `send`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DialogStep {
+    /// A regular expression the output must match, after the previous match
+    pub expect: Option<String>,
+    /// Text typed into the terminal
+    pub send: Option<String>,
+    /// How long to wait for `expect`, in milliseconds
+    pub timeout_ms: Option<u64>,
+}
+
+/// Check a parsed configuration for mistakes that the parser cannot catch
+///
+/// Returns a list of human-readable problems; an empty list means the configuration is valid.
+pub fn validate_config(config: &TestConfig) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if config.tests.is_empty() {
+        problems.push("No tests are defined".to_string());
+    }
+
+    let steps = config
+        .build
+        .as_ref()
+        .and_then(|b| b.steps.as_deref())
+        .unwrap_or_default();
+
+    let mut seen = std::collections::HashSet::new();
+    for (i, test) in config.tests.iter().enumerate() {
+        let label = format!("Test #{} ({:?})", i + 1, test.name);
+
+        if test.name.trim().is_empty() {
+            problems.push(format!("Test #{}: name must not be empty", i + 1));
+        } else if !seen.insert(test.name.as_str()) {
+            problems.push(format!("{}: duplicate test name", label));
+        }
+
+        match (&test.cargo_bin, test.command.trim().is_empty()) {
+            (None, true) => problems.push(format!("{}: command must not be empty", label)),
+            (Some(_), false) => problems.push(format!(
+                "{}: command and cargo_bin cannot be used together",
+                label
+            )),
+            _ => {}
+        }
+
+        if !test.has_expectation() {
+            problems.push(format!(
+                "{}: nothing to check; {}",
+                label, MISSING_EXPECTATION
+            ));
+        }
+
+        if test.timeout_secs == Some(0) {
+            problems.push(format!("{}: timeout_secs must be greater than 0", label));
+        }
+
+        if test.fixtures.is_some() && !test.is_isolated() {
+            problems.push(format!("{}: fixtures require isolate: true", label));
+        }
+        for fixture in test.fixtures.iter().flatten() {
+            if !fixture.exists() {
+                problems.push(format!(
+                    "{}: fixture not found: {}",
+                    label,
+                    fixture.display()
+                ));
+            }
+        }
+
+        if let Some(PtyOption::Settings(settings)) = &test.pty {
+            if settings.rows == Some(0) || settings.cols == Some(0) {
+                problems.push(format!(
+                    "{}: pty rows and cols must be greater than 0",
+                    label
+                ));
+            }
+        }
+
+        if let Some(screen) = &test.screen {
+            problems.extend(validate_screen(test, screen).map(|p| format!("{}: {}", label, p)));
+        }
+        if let Some(dialog) = &test.dialog {
+            problems.extend(validate_dialog(test, dialog).map(|p| format!("{}: {}", label, p)));
+        }
+
+        for (path, expected) in test.expected_files.iter().flatten() {
+            let ExpectedFile::Rule(rule) = expected else {
+                continue;
+            };
+            let file = format!("{}: expected file {}", label, path.display());
+            if rule.count() != 1 {
+                problems.push(format!(
+                    "{}: set exactly one of file, exists, absent and regex",
+                    file
+                ));
+            }
+            if let Some(reference) = rule.file.as_ref().filter(|f| !f.exists()) {
+                problems.push(format!("{}: file not found: {}", file, reference.display()));
+            }
+            if let Some(Err(e)) = rule.regex.as_deref().map(regex::Regex::new) {
+                problems.push(format!("{}: invalid regex: {}", file, e));
+            }
+        }
+
+        for name in test.needs.iter().flatten() {
+            if !steps.iter().any(|step| &step.name == name) {
+                problems.push(format!("{}: needs unknown build step '{}'", label, name));
+            }
+        }
+
+        if let Some(build) = &test.build {
+            if build.steps.is_some() || build.jobs.is_some() {
+                problems.push(format!(
+                    "{}: build steps can only be defined in the global build configuration",
+                    label
+                ));
+            }
+            problems.extend(validate_build(build).map(|p| format!("{}: {}", label, p)));
+        }
+    }
+
     if let Some(build) = &config.build {
-        if let Some(commands) = &build.pre_build_commands {
-            for cmd_template in commands {
-                // テンプレート変数を処理
-                let cmd = process_template(cmd_template, build.release);
+        if build.jobs == Some(0) {
+            problems.push("build: jobs must be greater than 0".to_string());
+        }
+        problems.extend(validate_build(build).map(|p| format!("build: {}", p)));
+        problems.extend(
+            graph::check(steps)
+                .into_iter()
+                .map(|p| format!("build: {}", p)),
+        );
+    }
 
-                // コマンド出力を抑制
-                // println!("Running pre-build command: {}", cmd);
+    problems
+}
 
-                // コマンドを実行
-                let output = Command::new("sh")
-                    .arg("-c")
-                    .arg(&cmd)
-                    .output()
-                    .with_context(|| format!("Failed to execute pre-build command: {}", cmd))?;
+// 画面のテストの設定を確かめる
+fn validate_screen(test: &TestCase, screen: &ScreenConfig) -> impl Iterator<Item = String> {
+    let mut problems = Vec::new();
+    if matches!(test.pty, Some(PtyOption::Flag(false))) {
+        problems.push("screen requires a pseudo-terminal; remove pty: false".to_string());
+    }
+    if test.input.is_some() || test.input_file.is_some() {
+        problems.push("screen cannot be used with input; send it with keys".to_string());
+    }
+    if test.expected_output.is_some() || test.expected_output_file.is_some() {
+        problems
+            .push("screen cannot be used with expected_output; use screen.expected".to_string());
+    }
 
-                if !output.status.success() {
-                    return Err(anyhow::anyhow!("Pre-build command failed: {}", cmd));
-                }
+    let steps = screen.keys.as_deref().unwrap_or_default();
+    let mut taken = Vec::new();
+    for (i, step) in steps.iter().enumerate() {
+        if *step == ScreenStep::default() {
+            problems.push(format!("screen step #{} does nothing", i + 1));
+        }
+        if let Some(key) = &step.key {
+            let known = pty::KEY_NAMES.contains(&key.to_ascii_lowercase().as_str())
+                || pty::ctrl_key(key).is_some();
+            if !known {
+                problems.push(format!("screen step #{}: unknown key {:?}", i + 1, key));
+            }
+        }
+        if let Some(name) = &step.snapshot {
+            if taken.contains(&name.as_str()) {
+                problems.push(format!("duplicate screen snapshot '{}'", name));
+            } else {
+                taken.push(name.as_str());
+            }
+        }
+    }
+
+    let expected = screen.snapshots.iter().flatten();
+    for name in expected.clone().map(|(name, _)| name) {
+        if !taken.contains(&name.as_str()) {
+            problems.push(format!("screen snapshot '{}' is never taken", name));
+        }
+    }
+    for name in taken {
+        if !expected.clone().any(|(expected, _)| expected == name) {
+            problems.push(format!("screen snapshot '{}' has no expected screen", name));
+        }
+    }
+    problems.into_iter()
+}
+
+// 対話のテストの設定を確かめる
+fn validate_dialog(test: &TestCase, dialog: &[DialogStep]) -> impl Iterator<Item = String> {
+    let mut problems = Vec::new();
+    if dialog.is_empty() {
+        problems.push("dialog has no steps".to_string());
+    }
+    if test.screen.is_some() {
+        problems.push("dialog and screen cannot be used together".to_string());
+    }
+    if matches!(test.pty, Some(PtyOption::Flag(false))) {
+        problems.push("dialog requires a pseudo-terminal; remove pty: false".to_string());
+    }
+    if test.input.is_some() || test.input_file.is_some() {
+        problems.push("dialog cannot be used with input; send it in the steps".to_string());
+    }
 
-                // コマンド出力を抑制
-                // let stdout = String::from_utf8_lossy(&output.stdout);
-                // println!("{}", stdout.trim());
+    for (i, step) in dialog.iter().enumerate() {
+        let label = format!("dialog step #{}", i + 1);
+        if step.expect.is_none() && step.send.is_none() {
+            problems.push(format!("{}: set expect, send or both", label));
+        }
+        match (&step.expect, step.timeout_ms) {
+            (None, Some(_)) => problems.push(format!("{}: timeout_ms requires expect", label)),
+            (_, Some(0)) => problems.push(format!("{}: timeout_ms must be greater than 0", label)),
+            _ => {}
+        }
+        if let Some(Err(e)) = step.expect.as_deref().map(regex::Regex::new) {
+            problems.push(format!("{}: invalid regex: {}", label, e));
+        }
+    }
+    problems.into_iter()
+}
+
+// 入力のグロブが正しいか確かめる
+fn validate_build(build: &BuildConfig) -> impl Iterator<Item = String> + '_ {
+    let command_inputs = build
+        .pre_build_commands
+        .iter()
+        .flatten()
+        .flat_map(PreBuildCommand::inputs);
+    let step_inputs = build
+        .steps
+        .iter()
+        .flatten()
+        .flat_map(|step| step.inputs.iter().flatten());
+    command_inputs.chain(step_inputs).filter_map(|glob| {
+        globset::Glob::new(glob)
+            .err()
+            .map(|e| format!("invalid input glob {:?}: {}", glob, e))
+    })
+}
+
+// ビルド前のコマンドを実行する関数（各ステップの出力は on_step に渡す）
+pub fn run_pre_build_commands(
+    config: &TestConfig,
+    builds: &BuildCache,
+    mut on_step: impl FnMut(&BuildStep),
+) -> Result<()> {
+    if let Some(build) = &config.build {
+        if let Some(commands) = &build.pre_build_commands {
+            for command in commands {
+                // テンプレート変数を処理
+                let cmd = process_template(command.command(), build.release);
+
+                // コマンドを実行して出力を記録（実行済みのものは飛ばす）
+                let Some(step) = builds.run(&cmd, command.inputs(), command.outputs(), None)?
+                else {
+                    continue;
+                };
+                on_step(&step);
+
+                if !step.success {
+                    return Err(anyhow::anyhow!(
+                        "Pre-build command failed: {}\n\n{}",
+                        cmd,
+                        step.failure_output()
+                    ));
+                }
             }
         }
     }
@@ -211,154 +1197,776 @@ pub fn run_pre_build_commands(config: &TestConfig) -> Result<()> {
     Ok(())
 }
 
-pub fn run_tests(config: &TestConfig) -> Result<Vec<TestResult>> {
-    // ビルド前のコマンドを実行
-    run_pre_build_commands(config)?;
+/// Options that control when a run stops early
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Stop after this many failing tests (`--fail-fast` is `Some(1)`)
+    pub max_failures: Option<usize>,
+    /// Time budget for the whole run, counted from its start
+    ///
+    /// The time spent in builds counts, but only tests are stopped when it runs out;
+    /// pre-build commands and build steps always run to the end.
+    pub max_duration: Option<Duration>,
+    /// Keep the temporary directories of failing isolated tests
+    pub keep_temp: bool,
+}
+
+/// Progress of a run, reported while the tests are running
+#[derive(Debug, Clone, Copy)]
+pub enum TestEvent<'a> {
+    /// A test is about to be executed (not sent for skipped or not-run tests)
+    Started { index: usize, test: &'a TestCase },
+    /// A test has a result, whether it was executed or not
+    Finished {
+        index: usize,
+        result: &'a TestResult,
+    },
+    /// A pre-build command or the build of a `cargo_bin` finished
+    BuildFinished { step: &'a BuildStep },
+}
+
+/// Number of results a run of this configuration reports (only the `only` tests if any)
+pub fn planned_count(config: &TestConfig) -> usize {
+    let focused = config.tests.iter().filter(|t| t.is_only()).count();
+    if focused > 0 {
+        focused
+    } else {
+        config.tests.len()
+    }
+}
 
-    let mut results = Vec::new();
-    let global_release = config.build.as_ref().map_or(false, |b| b.release);
+/// Results of a run, with the failure of the suite teardown kept apart from the tests
+#[derive(Debug, Clone, Default)]
+pub struct RunOutcome {
+    pub results: Vec<TestResult>,
+    /// Why the suite teardown failed, if it did
+    pub teardown_error: Option<String>,
+}
 
-    for test in &config.tests {
-        // コマンド出力を抑制
-        // println!("Running test: {}", test.name);
+impl RunOutcome {
+    /// Whether the run should fail: a test failed or the suite teardown did
+    pub fn has_failures(&self) -> bool {
+        TestStats::from_results(&self.results).has_failures() || self.teardown_error.is_some()
+    }
+}
 
-        // テスト固有のビルド設定があれば実行
-        if let Some(build) = &test.build {
-            run_test_build_commands(test, build)?;
+pub fn run_tests(config: &TestConfig, options: &RunOptions) -> Result<RunOutcome> {
+    run_tests_with(config, options, |_| {})
+}
+
+/// Same as [`run_tests`], calling `on_event` as each test starts and finishes
+pub fn run_tests_with(
+    config: &TestConfig,
+    options: &RunOptions,
+    mut on_event: impl FnMut(TestEvent),
+) -> Result<RunOutcome> {
+    // 表せないほど遠い期限は期限なしと同じ
+    let deadline = options
+        .max_duration
+        .and_then(|d| Instant::now().checked_add(d));
+
+    // ビルド前のコマンドを実行
+    let builds = BuildCache::load();
+    run_pre_build_commands(config, &builds, |step| {
+        on_event(TestEvent::BuildFinished { step })
+    })
+    .context(ErrorKind::BuildFailed)?;
+
+    // 名前付きのビルドステップは別スレッドで依存関係の順に実行する
+    let build = config.build.as_ref();
+    let release = build.is_some_and(|b| b.release);
+    let steps = build.and_then(|b| b.steps.as_deref()).unwrap_or_default();
+    let graph = BuildGraph::new(steps, |command| process_template(command, release))
+        .context(ErrorKind::ConfigInvalid)?;
+    let jobs = build
+        .and_then(|b| b.jobs)
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+
+    std::thread::scope(|scope| {
+        graph.start(scope, &builds, jobs);
+
+        // cargo_bin のバイナリは実行ごとに一度だけビルドする
+        let mut binaries = cargo::BinaryCache::default();
+        let mut results: Vec<TestResult> = Vec::new();
+        let mut failures = 0;
+        // 途中で打ち切った場合、残りのテストは未実行として記録する
+        let mut stop_reason: Option<String> = None;
+
+        // only が指定されたテストがあれば、それ以外は実行しない
+        let focused = config.tests.iter().any(TestCase::is_only);
+
+        // スイートの setup が失敗したら、テストは実行せずに失敗として記録する
+        let suite_setup = run_hooks(
+            config.setup.as_deref().unwrap_or_default(),
+            "suite setup",
+            None,
+            None,
+            &mut |step| on_event(TestEvent::BuildFinished { step }),
+        )
+        .err();
+
+        for test in config.tests.iter().filter(|t| !focused || t.is_only()) {
+            // コマンド出力を抑制
+            // println!("Running test: {}", test.name);
+
+            if stop_reason.is_none() && deadline.is_some_and(|d| Instant::now() >= d) {
+                stop_reason = Some(format!(
+                    "time budget of {}s exceeded",
+                    options.max_duration.unwrap_or_default().as_secs_f64()
+                ));
+                graph.cancel();
+            }
+
+            let index = results.len();
+            let result = if let Some(reason) = &stop_reason {
+                unexecuted_result(config, test, TestStatus::NotRun, Some(reason.clone()))
+            } else if test.is_skipped() {
+                let reason = test
+                    .skip
+                    .as_ref()
+                    .and_then(Marker::reason)
+                    .map(String::from);
+                unexecuted_result(config, test, TestStatus::Skipped, reason)
+            } else {
+                on_event(TestEvent::Started { index, test });
+
+                // 必要なビルドステップを待ってから実行する
+                // コマンドが起動できないなどのエラーはテスト単位のエラーとして記録する
+                let mut on_step = |step: &BuildStep| on_event(TestEvent::BuildFinished { step });
+                let result = match (
+                    &suite_setup,
+                    graph.wait(test.needs.as_deref(), &mut on_step),
+                ) {
+                    (Some(reason), _) => unexecuted_result(
+                        config,
+                        test,
+                        TestStatus::HookFailed,
+                        Some(reason.clone()),
+                    ),
+                    (None, Ok(())) => run_test_with_hooks(
+                        config,
+                        test,
+                        options,
+                        deadline,
+                        &mut binaries,
+                        &builds,
+                        &mut on_step,
+                    ),
+                    (None, Err(reason)) => {
+                        unexecuted_result(config, test, TestStatus::Error, Some(reason))
+                    }
+                };
+                if result.status.is_failure() {
+                    failures += 1;
+                    if options.max_failures.is_some_and(|max| failures >= max) {
+                        stop_reason = Some(format!("run stopped after {} failure(s)", failures));
+                        graph.cancel();
+                    }
+                }
+                result
+            };
+
+            on_event(TestEvent::Finished {
+                index,
+                result: &result,
+            });
+            results.push(result);
         }
 
-        // テスト固有のリリースモード設定があればそれを使用、なければグローバル設定を使用
-        let is_release = test.build.as_ref().map_or(global_release, |b| b.release);
+        // 実行中のステップを待ち、残りの出力を報告する
+        graph.finish(&mut |step| on_event(TestEvent::BuildFinished { step }));
+
+        // スイートの teardown は打ち切られた場合も実行し、失敗はテストの結果とは別に返す
+        let teardown_error = run_hooks(
+            config.teardown.as_deref().unwrap_or_default(),
+            "suite teardown",
+            None,
+            None,
+            &mut |step| on_event(TestEvent::BuildFinished { step }),
+        )
+        .err();
+        Ok(RunOutcome {
+            results,
+            teardown_error,
+        })
+    })
+}
 
-        let mut command = Command::new(&test.command);
+// テストの前後に setup / teardown を実行する（teardown はテストの結果にかかわらず実行する）
+fn run_test_with_hooks(
+    config: &TestConfig,
+    test: &TestCase,
+    options: &RunOptions,
+    deadline: Option<Instant>,
+    binaries: &mut cargo::BinaryCache,
+    builds: &BuildCache,
+    on_step: &mut dyn FnMut(&BuildStep),
+) -> TestResult {
+    // isolate のテストはフックも含めて一時ディレクトリで実行する
+    let temp_dir = if test.is_isolated() {
+        let fixtures = test.fixtures.as_deref().unwrap_or_default();
+        match TempDir::create(&test.name, fixtures) {
+            Ok(dir) => Some(dir),
+            Err(e) => {
+                let reason = Some(format!("{:#}", e));
+                return unexecuted_result(config, test, TestStatus::Error, reason);
+            }
+        }
+    } else {
+        None
+    };
+    let dir = temp_dir.as_ref().map(TempDir::path);
+
+    let setup = test.setup.as_deref().unwrap_or_default();
+    let mut result = match run_hooks(setup, "setup", Some(&test.name), dir, on_step) {
+        // コマンドが起動できないなどのエラーはテスト単位のエラーとして記録する
+        Ok(()) => {
+            run_test(config, test, dir, deadline, binaries, builds, on_step).unwrap_or_else(|e| {
+                unexecuted_result(config, test, TestStatus::Error, Some(format!("{:#}", e)))
+            })
+        }
+        Err(reason) => unexecuted_result(config, test, TestStatus::HookFailed, Some(reason)),
+    };
+
+    let teardown = test.teardown.as_deref().unwrap_or_default();
+    if let Err(reason) = run_hooks(teardown, "teardown", Some(&test.name), dir, on_step) {
+        // 失敗したテストの結果はそのままにして、理由に追記する
+        result.reason = Some(match result.reason.take() {
+            Some(previous) if result.status.is_failure() => format!("{}\n{}", previous, reason),
+            _ => reason,
+        });
+        if !result.status.is_failure() {
+            result.status = TestStatus::HookFailed;
+        }
+    }
 
-        // Process arguments if provided
-        let processed_args = if let Some(args) = &test.args {
-            // テンプレート変数を処理
-            let processed: Vec<String> = args
-                .iter()
-                .map(|arg| process_template(arg, is_release))
-                .collect();
+    // 失敗したテストのディレクトリは調べられるように残す
+    if let Some(dir) = temp_dir {
+        if options.keep_temp && result.status.is_failure() {
+            result.temp_dir = Some(dir.keep());
+        }
+    }
+    result
+}
 
-            command.args(&processed);
-            processed
-        } else {
-            Vec::new()
+/// Run setup or teardown commands in `dir` (the working directory if `None`)
+///
+/// Each command is reported as a build step. Setup stops at the first failure; teardown runs
+/// every command and returns the first failure.
+fn run_hooks(
+    commands: &[String],
+    kind: &str,
+    test: Option<&str>,
+    dir: Option<&Path>,
+    on_step: &mut dyn FnMut(&BuildStep),
+) -> Result<(), String> {
+    let keep_going = kind.ends_with("teardown");
+    let mut failure = None;
+    for command in commands {
+        let mut process = Command::new("sh");
+        process.arg("-c").arg(command);
+        if let Some(dir) = dir {
+            process.current_dir(dir);
+        }
+        let reason = match build::run(process, command, test) {
+            Ok(mut step) => {
+                step.kind = StepKind::Hook;
+                step.name = Some(kind.to_string());
+                on_step(&step);
+                (!step.success).then(|| {
+                    let message = format!("{} command failed: {}", kind, command);
+                    match step.failure_output() {
+                        output if output.trim().is_empty() => message,
+                        output => format!("{}\n\n{}", message, output),
+                    }
+                })
+            }
+            Err(e) => Some(format!(
+                "{} command could not run: {}: {:#}",
+                kind, command, e
+            )),
         };
+        if failure.is_none() {
+            failure = reason;
+        }
+        if failure.is_some() && !keep_going {
+            break;
+        }
+    }
+    failure.map_or(Ok(()), Err)
+}
+
+fn run_test(
+    config: &TestConfig,
+    test: &TestCase,
+    dir: Option<&Path>,
+    deadline: Option<Instant>,
+    binaries: &mut cargo::BinaryCache,
+    builds: &BuildCache,
+    on_step: &mut dyn FnMut(&BuildStep),
+) -> Result<TestResult> {
+    // テスト固有のビルド設定があれば実行
+    if let Some(build) = &test.build {
+        run_test_build_commands(test, build, builds, on_step)?;
+    }
 
-        // Setup stdin if input is provided
-        let start_time = std::time::Instant::now();
+    // テスト固有のリリースモード設定があればそれを使用、なければグローバル設定を使用
+    let is_release = is_release_for(config, test);
+
+    let program = match &test.cargo_bin {
+        Some(cargo_bin) => binaries
+            .resolve(cargo_bin, is_release, Some(&test.name), on_step)?
+            .display()
+            .to_string(),
+        // 一時ディレクトリで実行するので、相対パスのコマンドは絶対パスにする
+        None if dir.is_some() => isolate::resolve_program(&test.command),
+        None => test.command.clone(),
+    };
+
+    // Process arguments if provided
+    let processed_args = resolve_args(test, is_release);
+
+    // xfail のテストは失敗が期待されているので再試行しない
+    let retries = if test.is_xfail() {
+        0
+    } else {
+        retries_for(config, test)
+    };
+
+    let mut attempts = Vec::new();
+    for _ in 0..=retries {
+        let attempt = run_attempt(test, &program, &processed_args, dir, deadline)?;
+        let success = attempt.success;
+        attempts.push(attempt);
+        if success {
+            break;
+        }
+    }
 
-        let mut child = if let Some(_input) = &test.input {
-            command
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .spawn()
-                .with_context(|| format!("Failed to spawn command: {}", test.command))?
-        } else {
-            command
-                .stdout(Stdio::piped())
-                .spawn()
-                .with_context(|| format!("Failed to spawn command: {}", test.command))?
-        };
+    let last = attempts
+        .last()
+        .expect("at least one attempt is made")
+        .clone();
+    let success = last.success;
+
+    // xfail の場合は失敗が期待される結果になる
+    let status = match (test.is_xfail(), success) {
+        (false, true) if attempts.len() > 1 => TestStatus::Flaky,
+        (false, true) => TestStatus::Passed,
+        (false, false) => TestStatus::Failed,
+        (true, false) => TestStatus::XFailed,
+        (true, true) => TestStatus::XPassed,
+    };
+    let reason = if test.is_xfail() {
+        test.xfail
+            .as_ref()
+            .and_then(Marker::reason)
+            .map(String::from)
+    } else if last.timed_out && deadline.is_some_and(|d| Instant::now() >= d) {
+        Some("killed when the time budget of the run ran out".to_string())
+    } else if last.timed_out {
+        Some(format!(
+            "timed out after {}s",
+            test.timeout_secs.unwrap_or(30)
+        ))
+    } else {
+        None
+    };
+
+    // Extract build commands if available
+    let build_commands = test.build.as_ref().and_then(build_command_list);
+
+    Ok(TestResult {
+        name: test.name.clone(),
+        success,
+        status,
+        reason,
+        actual_output: last.actual_output,
+        stderr: last.stderr,
+        diff: last.diff,
+        command: program,
+        args: processed_args,
+        input: test.input.clone(),
+        execution_time: last.execution_time,
+        is_release,
+        build_commands,
+        tags: test.tags.clone().unwrap_or_default(),
+        attempts,
+        file_checks: last.file_checks,
+        snapshot_checks: last.snapshot_checks,
+        dialog: last.dialog,
+        temp_dir: None,
+    })
+}
 
-        // Write to stdin if input is provided
-        if let Some(input) = &test.input {
-            if let Some(mut stdin) = child.stdin.take() {
-                stdin
-                    .write_all(input.as_bytes())
-                    .context("Failed to write to stdin")?;
-                // 標準入力をクローズして、コマンドが入力の終了を認識できるようにする
-                // drop(stdin)は自動的に行われる
-            }
+/// Number of retries for a test (per-test setting wins over the global one)
+pub fn retries_for(config: &TestConfig, test: &TestCase) -> u32 {
+    test.retries.or(config.retries).unwrap_or(0)
+}
+
+// テストのコマンドを 1 回実行して出力を比較する
+fn run_attempt(
+    test: &TestCase,
+    program: &str,
+    args: &[String],
+    dir: Option<&Path>,
+    deadline: Option<Instant>,
+) -> Result<TestAttempt> {
+    // Get output with timeout (never beyond the time budget of the run)
+    let mut timeout = Duration::from_secs(test.timeout_secs.unwrap_or(30));
+    if let Some(deadline) = deadline {
+        timeout = timeout.min(deadline.saturating_duration_since(Instant::now()));
+    }
+
+    let pty = test.pty.as_ref().and_then(PtyOption::settings);
+    let mut snapshot_checks = Vec::new();
+    let mut dialog = Vec::new();
+    let (actual_output, stderr, execution_time, timed_out) = match (&test.screen, pty) {
+        // 画面のテストでは、終了後の画面を出力として比べる
+        (Some(screen), pty) => {
+            let steps = screen.keys.as_deref().unwrap_or_default();
+            let size = pty.unwrap_or_default().size();
+            let output = pty::run_screen(program, args, dir, size, steps, timeout)?;
+            snapshot_checks = check_snapshots(screen, &output.snapshots);
+            (
+                output.screen,
+                String::new(),
+                output.duration,
+                output.timed_out,
+            )
+        }
+        (None, pty) if test.dialog.is_some() => {
+            let steps = test.dialog.as_deref().unwrap_or_default();
+            let settings = pty.unwrap_or_default();
+            let output = pty::run_dialog(program, args, dir, settings.size(), steps, timeout)?;
+            let text = output.text(settings.strip_ansi.unwrap_or(false));
+            dialog = output.transcript;
+            (text, String::new(), output.duration, output.timed_out)
         }
+        // 端末では標準エラー出力も同じ画面に出るので、出力は 1 つになる
+        (None, Some(settings)) => {
+            let input = test.input.as_deref();
+            let output = pty::run(program, args, dir, input, settings.size(), timeout)?;
+            let text = output.text(settings.strip_ansi.unwrap_or(false));
+            (text, String::new(), output.duration, output.timed_out)
+        }
+        (None, None) => run_piped(test, program, args, dir, timeout)?,
+    };
+
+    // 画面は行頭の空白にも意味があるので、行末の空白と末尾の空行だけを無視する
+    // 出力全体は expected_output があるときだけ比べる（対話やファイルだけを確かめるテストもある）
+    let expected = match &test.screen {
+        Some(screen) => screen.expected.as_deref().map(pty::normalize_screen),
+        None => test.expected_output.clone(),
+    };
+    let output_matches = !timed_out
+        && expected.as_ref().is_none_or(|expected| match &test.screen {
+            Some(_) => *expected == actual_output,
+            None => actual_output.trim() == expected.trim(),
+        });
+    let file_checks = check_files(test, dir);
+    let success = output_matches
+        && file_checks.iter().all(|check| check.success)
+        && snapshot_checks.iter().all(|check| check.success)
+        && dialog.iter().all(|event| event.success);
+
+    // Generate diff if the output did not match
+    let diff = if !output_matches {
+        Some(diff_lines(&expected.unwrap_or_default(), &actual_output))
+    } else {
+        None
+    };
+
+    Ok(TestAttempt {
+        success,
+        actual_output,
+        stderr,
+        diff,
+        execution_time,
+        timed_out,
+        file_checks,
+        snapshot_checks,
+        dialog,
+    })
+}
 
-        // Get output with timeout
-        let timeout = Duration::from_secs(test.timeout_secs.unwrap_or(30));
-        let output_status = child
-            .wait_timeout(timeout)
-            .context("Command execution failed")?;
+// パイプにつないでコマンドを実行し、標準出力、標準エラー出力、実行時間、タイムアウトしたかを返す
+fn run_piped(
+    test: &TestCase,
+    program: &str,
+    args: &[String],
+    dir: Option<&Path>,
+    timeout: Duration,
+) -> Result<(String, String, Duration, bool)> {
+    let mut command = Command::new(program);
+    command.args(args);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    // タイムアウトしたときにコマンドが起動したプロセスもまとめて止められるよう、
+    // 別のプロセスグループで実行する
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+    // Setup stdin if input is provided
+    let start_time = std::time::Instant::now();
+
+    let mut child = if let Some(_input) = &test.input {
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to spawn command: {}", program))?
+    } else {
+        command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to spawn command: {}", program))?
+    };
+
+    // 出力が多いとパイプが詰まるので、別スレッドで読み続ける
+    let stdout_reader = spawn_reader(child.stdout.take());
+    let stderr_reader = spawn_reader(child.stderr.take());
+
+    // Write to stdin if input is provided
+    if let Some(input) = &test.input {
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(input.as_bytes())
+                .context("Failed to write to stdin")?;
+            // 標準入力をクローズして、コマンドが入力の終了を認識できるようにする
+            // drop(stdin)は自動的に行われる
+        }
+    }
 
-        let execution_time = start_time.elapsed();
+    let output_status = child
+        .wait_timeout(timeout)
+        .context("Command execution failed")?;
 
-        let output = if output_status.is_some() {
-            child.wait_with_output()?
-        } else {
-            child.kill()?;
-            return Err(anyhow::anyhow!("Command timed out: {}", test.name));
-        };
+    let execution_time = start_time.elapsed();
 
-        let actual_output = String::from_utf8_lossy(&output.stdout).to_string();
-        let success = actual_output.trim() == test.expected_output.trim();
+    // タイムアウトした場合はプロセスを止めて、それまでの出力で失敗扱いにする
+    // 孫プロセスがパイプを開いたままだと読み込みが終わらないので、グループごと止める
+    let timed_out = output_status.is_none();
+    if timed_out {
+        kill_process_group(&mut child)?;
+        child.wait()?;
+    }
 
-        // Generate diff if test failed
-        let diff = if !success {
-            let text_diff = TextDiff::from_lines(&test.expected_output, &actual_output);
+    let actual_output =
+        String::from_utf8_lossy(&stdout_reader.join().unwrap_or_default()).to_string();
+    let stderr = String::from_utf8_lossy(&stderr_reader.join().unwrap_or_default()).to_string();
+    Ok((actual_output, stderr, execution_time, timed_out))
+}
 
-            let mut diff_lines = Vec::new();
-            for change in text_diff.iter_all_changes() {
-                diff_lines.push(DiffLine {
-                    tag: change.tag(),
-                    content: change.value().to_string(),
-                });
+// 名前付きのスナップショットを期待する画面と比べる
+fn check_snapshots(screen: &ScreenConfig, taken: &[(String, String)]) -> Vec<SnapshotCheck> {
+    screen
+        .snapshots
+        .iter()
+        .flatten()
+        .map(|(name, expected)| {
+            let actual = taken.iter().find(|(n, _)| n == name).map(|(_, s)| s);
+            let expected = pty::normalize_screen(expected);
+            let (message, diff) = match actual {
+                // 時間切れなどで手順の途中で終わった
+                None => (Some("snapshot was not taken".to_string()), None),
+                Some(actual) if *actual == expected => (None, None),
+                Some(actual) => (None, Some(diff_lines(&expected, actual))),
+            };
+            SnapshotCheck {
+                name: name.clone(),
+                success: message.is_none() && diff.is_none(),
+                message,
+                diff,
             }
+        })
+        .collect()
+}
 
-            Some(diff_lines)
-        } else {
-            None
-        };
+// expected_files のファイルを確かめる（パスはコマンドを実行したディレクトリからの相対パス）
+fn check_files(test: &TestCase, dir: Option<&Path>) -> Vec<FileCheck> {
+    let Some(expected_files) = &test.expected_files else {
+        return Vec::new();
+    };
+    expected_files
+        .iter()
+        .map(|(path, expected)| {
+            let actual_path = dir.map_or_else(|| path.clone(), |dir| dir.join(path));
+            let actual = std::fs::read(&actual_path)
+                .ok()
+                .map(|bytes| String::from_utf8_lossy(&bytes).to_string());
+            let (message, diff) = check_file(expected, actual.as_deref());
+            FileCheck {
+                path: path.clone(),
+                success: message.is_none() && diff.is_none(),
+                message,
+                diff,
+            }
+        })
+        .collect()
+}
 
-        // Extract build commands if available
-        let build_commands = test
-            .build
-            .as_ref()
-            .and_then(|b| b.pre_build_commands.clone());
-
-        results.push(TestResult {
-            name: test.name.clone(),
-            success,
-            actual_output,
-            diff,
-            command: test.command.clone(),
-            args: processed_args,
-            input: test.input.clone(),
-            execution_time,
-            is_release,
-            build_commands,
+// 1 つのファイルの内容を規則と比べ、失敗の説明か差分を返す
+fn check_file(
+    expected: &ExpectedFile,
+    actual: Option<&str>,
+) -> (Option<String>, Option<Vec<DiffLine>>) {
+    let missing = || (Some("file was not created".to_string()), None);
+    let compare = |expected: &str| match actual {
+        None => missing(),
+        // 標準出力と同じく前後の空白は無視する
+        Some(actual) if actual.trim() == expected.trim() => (None, None),
+        Some(actual) => (None, Some(diff_lines(expected, actual))),
+    };
+
+    match expected {
+        ExpectedFile::Content(content) => compare(content),
+        ExpectedFile::Rule(rule) => {
+            if let Some(reference) = &rule.file {
+                return match std::fs::read_to_string(reference) {
+                    Ok(content) => compare(&content),
+                    Err(e) => (
+                        Some(format!(
+                            "cannot read the expected content from {}: {}",
+                            reference.display(),
+                            e
+                        )),
+                        None,
+                    ),
+                };
+            }
+            if let Some(pattern) = &rule.regex {
+                let Some(actual) = actual else {
+                    return missing();
+                };
+                return match regex::Regex::new(pattern) {
+                    Ok(regex) if regex.is_match(actual) => (None, None),
+                    Ok(_) => (Some(format!("content does not match /{}/", pattern)), None),
+                    Err(e) => (Some(format!("invalid regex: {}", e)), None),
+                };
+            }
+            let must_exist = rule.exists.or(rule.absent.map(|absent| !absent));
+            match (must_exist, actual.is_some()) {
+                (Some(true), false) => missing(),
+                (Some(false), true) => (Some("file should not exist".to_string()), None),
+                _ => (None, None),
+            }
+        }
+    }
+}
+
+// コマンドとそれが起動したプロセスを止める
+fn kill_process_group(child: &mut std::process::Child) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        // SAFETY: killpg does not touch memory; the group was created for this child
+        if unsafe { libc::killpg(child.id() as libc::pid_t, libc::SIGKILL) } == 0 {
+            return Ok(());
+        }
+    }
+    child.kill()
+}
+
+// パイプの内容を最後まで読み込むスレッドを起動する
+fn spawn_reader<R: Read + Send + 'static>(pipe: Option<R>) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            // 読み込みエラーはそれまでに読めた分だけを返す
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+/// Line diff between the expected and the actual text
+pub fn diff_lines(expected: &str, actual: &str) -> Vec<DiffLine> {
+    let text_diff = TextDiff::from_lines(expected, actual);
+
+    let mut diff_lines = Vec::new();
+    for change in text_diff.iter_all_changes() {
+        diff_lines.push(DiffLine {
+            tag: change.tag(),
+            content: change.value().to_string(),
         });
     }
 
-    Ok(results)
+    diff_lines
+}
+
+// 結果に表示するビルドコマンドの一覧
+fn build_command_list(build: &BuildConfig) -> Option<Vec<String>> {
+    build.pre_build_commands.as_ref().map(|commands| {
+        commands
+            .iter()
+            .map(|command| command.command().to_string())
+            .collect()
+    })
+}
+
+// 実行しなかったテスト（スキップや打ち切り）の結果を作成する
+fn unexecuted_result(
+    config: &TestConfig,
+    test: &TestCase,
+    status: TestStatus,
+    reason: Option<String>,
+) -> TestResult {
+    let is_release = is_release_for(config, test);
+
+    TestResult {
+        name: test.name.clone(),
+        success: false,
+        status,
+        reason,
+        actual_output: String::new(),
+        stderr: String::new(),
+        diff: None,
+        command: test.command_label(),
+        args: resolve_args(test, is_release),
+        input: test.input.clone(),
+        execution_time: Duration::ZERO,
+        is_release,
+        build_commands: test.build.as_ref().and_then(build_command_list),
+        tags: test.tags.clone().unwrap_or_default(),
+        attempts: Vec::new(),
+        file_checks: Vec::new(),
+        snapshot_checks: Vec::new(),
+        dialog: Vec::new(),
+        temp_dir: None,
+    }
 }
 
 // テスト固有のビルドコマンドを実行する関数
-fn run_test_build_commands(test: &TestCase, build: &BuildConfig) -> Result<()> {
+fn run_test_build_commands(
+    test: &TestCase,
+    build: &BuildConfig,
+    builds: &BuildCache,
+    on_step: &mut dyn FnMut(&BuildStep),
+) -> Result<()> {
     if let Some(commands) = &build.pre_build_commands {
-        for cmd_template in commands {
+        for command in commands {
             // テンプレート変数を処理
-            let cmd = process_template(cmd_template, build.release);
-
-            // コマンド出力を抑制
-            // println!("Running pre-build command for test '{}': {}", test.name, cmd);
+            let cmd = process_template(command.command(), build.release);
 
-            // コマンドを実行
-            let output = Command::new("sh")
-                .arg("-c")
-                .arg(&cmd)
-                .output()
-                .with_context(|| format!("Failed to execute pre-build command: {}", cmd))?;
+            // コマンドを実行して出力を記録（同じ実行の中で成功済みのものは飛ばす）
+            let Some(step) =
+                builds.run(&cmd, command.inputs(), command.outputs(), Some(&test.name))?
+            else {
+                continue;
+            };
+            on_step(&step);
 
-            if !output.status.success() {
+            if !step.success {
                 return Err(anyhow::anyhow!(
-                    "Pre-build command failed for test '{}': {}",
+                    "Pre-build command failed for test '{}': {}\n\n{}",
                     test.name,
-                    cmd
+                    cmd,
+                    step.failure_output()
                 ));
             }
-
-            // コマンド出力を抑制
-            // let stdout = String::from_utf8_lossy(&output.stdout);
-            // println!("{}", stdout.trim());
         }
     }
 
@@ -393,3 +2001,272 @@ impl CommandExt for std::process::Child {
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> TestConfig {
+        serde_yaml::from_str(yaml).expect("valid test configuration")
+    }
+
+    #[test]
+    fn tests_need_something_to_check() {
+        let config = config(
+            r#"
+tests:
+  - name: "Misspelled"
+    command: "echo"
+    expected_ouput: "hi"
+  - name: "Prints nothing"
+    command: "true"
+    expected_output: ""
+  - name: "Only files"
+    command: "touch"
+    args: ["out.txt"]
+    expected_files:
+      out.txt: { exists: true }
+"#,
+        );
+
+        assert_eq!(config.tests[1].expected_output.as_deref(), Some(""));
+        assert_eq!(
+            validate_config(&config),
+            vec![format!(
+                "Test #1 (\"Misspelled\"): nothing to check; {}",
+                MISSING_EXPECTATION
+            )]
+        );
+
+        let path = std::env::temp_dir().join(format!("yamori-expect-{}.yaml", std::process::id()));
+        std::fs::write(&path, serde_yaml::to_string(&config).unwrap()).unwrap();
+        let error = load_config(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("Test 'Misspelled' has nothing to check"));
+    }
+
+    #[test]
+    fn find_test_line_only_looks_at_tests() {
+        let yaml = r#"build:
+  steps:
+    - name: "Echo"
+      command: "make echo"
+setup:
+  - "echo ready"
+tests:
+  - name: "Other"
+    command: "true"
+    expected_output: ""
+  - command: "echo"
+    name: 'Echo' # the test
+    expected_output: "hi"
+"#;
+        assert_eq!(find_test_line(yaml, "Echo"), Some(12));
+        assert_eq!(find_test_line(yaml, "Other"), Some(8));
+        assert_eq!(find_test_line(yaml, "Missing"), None);
+
+        let toml = r#"[[build.steps]]
+name = "Echo"
+command = "make echo"
+
+[[tests]]
+name = "Echo"
+command = "echo"
+expected_output = "hi"
+
+[tests.build]
+release = true
+"#;
+        assert_eq!(find_test_line(toml, "Echo"), Some(6));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timeout_stops_the_processes_started_by_the_command() {
+        let config = config(
+            r#"
+tests:
+  - name: "Hangs in a child process"
+    command: "sh"
+    args: ["-c", "sleep 8; echo done"]
+    expected_output: "done"
+    timeout_secs: 1
+"#,
+        );
+
+        let start = Instant::now();
+        let results = run_tests(&config, &RunOptions::default()).unwrap().results;
+
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(results[0].status, TestStatus::Failed);
+        assert!(results[0].attempts.iter().all(|a| a.timed_out));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_failed_suite_teardown_is_not_a_test_result() {
+        let config = config(
+            r#"
+teardown: ["false"]
+tests:
+  - name: "Echo"
+    command: "echo"
+    args: ["hi"]
+    expected_output: "hi"
+"#,
+        );
+
+        let outcome = run_tests(&config, &RunOptions::default()).unwrap();
+
+        assert_eq!(outcome.results.len(), 1);
+        assert_eq!(outcome.results[0].status, TestStatus::Passed);
+        assert!(outcome
+            .teardown_error
+            .as_deref()
+            .is_some_and(|reason| reason.contains("suite teardown")));
+        assert!(outcome.has_failures());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn temp_dirs_of_isolated_tests_are_removed_unless_kept() {
+        let config = config(
+            r#"
+tests:
+  - name: "Passes in a temp dir"
+    command: "sh"
+    args: ["-c", "pwd >&2"]
+    expected_output: ""
+    isolate: true
+  - name: "Fails in a temp dir"
+    command: "sh"
+    args: ["-c", "pwd >&2; echo wrong"]
+    expected_output: "right"
+    isolate: true
+"#,
+        );
+        let dir_of = |result: &TestResult| PathBuf::from(result.stderr.trim_end());
+
+        let results = run_tests(&config, &RunOptions::default()).unwrap().results;
+        assert_eq!(results[0].status, TestStatus::Passed);
+        assert_eq!(results[1].status, TestStatus::Failed);
+        for result in &results {
+            assert!(result.temp_dir.is_none());
+            assert!(!dir_of(result).exists(), "{} was kept", result.name);
+        }
+
+        let options = RunOptions {
+            keep_temp: true,
+            ..RunOptions::default()
+        };
+        let results = run_tests(&config, &options).unwrap().results;
+        // 成功したテストのディレクトリは --keep-temp でも残さない
+        assert!(results[0].temp_dir.is_none());
+        assert!(!dir_of(&results[0]).exists());
+        let kept = results[1]
+            .temp_dir
+            .clone()
+            .expect("failing test keeps its dir");
+        assert!(kept.is_dir());
+        assert_eq!(
+            kept.canonicalize().unwrap(),
+            dir_of(&results[1]).canonicalize().unwrap()
+        );
+        std::fs::remove_dir_all(kept).unwrap();
+    }
+
+    #[test]
+    fn check_file_reports_missing_unexpected_and_matching_files() {
+        // 差分は有無だけを比べる
+        let verdict = |expected: &ExpectedFile, actual: Option<&str>| {
+            let (message, diff) = check_file(expected, actual);
+            (message, diff.is_some())
+        };
+
+        let content = ExpectedFile::Content("line 1\nline 2\n".to_string());
+        assert_eq!(verdict(&content, Some("line 1\nline 2")), (None, false));
+        assert_eq!(
+            verdict(&content, None),
+            (Some("file was not created".to_string()), false)
+        );
+        let (message, diff) = check_file(&content, Some("line 1\nline 3\n"));
+        assert!(message.is_none());
+        let changed: Vec<(ChangeTag, &str)> = diff
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .filter(|line| line.tag != ChangeTag::Equal)
+            .map(|line| (line.tag, line.content.as_str()))
+            .collect();
+        assert_eq!(
+            changed,
+            [
+                (ChangeTag::Delete, "line 2\n"),
+                (ChangeTag::Insert, "line 3\n")
+            ]
+        );
+
+        let rule = |rule: FileRule| ExpectedFile::Rule(rule);
+        let regex = rule(FileRule {
+            regex: Some("^id=\\d+$".to_string()),
+            ..FileRule::default()
+        });
+        assert_eq!(verdict(&regex, Some("id=42")), (None, false));
+        assert_eq!(
+            verdict(&regex, Some("id=x")),
+            (
+                Some("content does not match /^id=\\d+$/".to_string()),
+                false
+            )
+        );
+        assert_eq!(
+            verdict(&regex, None),
+            (Some("file was not created".to_string()), false)
+        );
+
+        let exists = rule(FileRule {
+            exists: Some(true),
+            ..FileRule::default()
+        });
+        assert_eq!(verdict(&exists, Some("")), (None, false));
+        assert!(verdict(&exists, None).0.is_some());
+        let absent = rule(FileRule {
+            absent: Some(true),
+            ..FileRule::default()
+        });
+        assert_eq!(verdict(&absent, None), (None, false));
+        assert_eq!(
+            verdict(&absent, Some("")),
+            (Some("file should not exist".to_string()), false)
+        );
+    }
+
+    #[test]
+    fn check_files_reads_the_files_in_the_test_dir() {
+        let dir = TempDir::create("check files", &[]).unwrap();
+        std::fs::write(dir.path().join("out.txt"), "done\n").unwrap();
+        let test = config(
+            r#"
+tests:
+  - name: "Writes files"
+    command: "true"
+    expected_files:
+      out.txt: "done"
+      missing.txt: "anything"
+"#,
+        )
+        .tests
+        .remove(0);
+
+        let checks = check_files(&test, Some(dir.path()));
+
+        let outcome: Vec<(&Path, bool)> = checks
+            .iter()
+            .map(|check| (check.path.as_path(), check.success))
+            .collect();
+        assert!(outcome.contains(&(Path::new("out.txt"), true)));
+        assert!(outcome.contains(&(Path::new("missing.txt"), false)));
+    }
+}
@@ -1,7 +1,6 @@
 use crate::app::{App, PopupType};
 use chrono::{DateTime, TimeZone, Utc};
 use ratatui::{
-    backend::Backend,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line as TextLine, Span},
@@ -13,12 +12,44 @@ use ratatui::{
 };
 use similar::ChangeTag;
 
-pub fn render_ui<B: Backend>(frame: &mut Frame, app: &App) {
+use crate::test::{DialogEvent, DiffLine, TestStatus};
+
+// テスト結果の状態ごとの記号
+fn status_symbol(status: TestStatus) -> &'static str {
+    match status {
+        TestStatus::Passed => "✓",
+        TestStatus::Failed => "✗",
+        TestStatus::Skipped => "⊘",
+        TestStatus::XFailed => "✗",
+        TestStatus::XPassed => "!",
+        TestStatus::Flaky => "~",
+        TestStatus::NotRun => "-",
+        TestStatus::Error => "E",
+        TestStatus::HookFailed => "H",
+    }
+}
+
+// テスト結果の状態ごとの色
+fn status_color(status: TestStatus) -> Color {
+    match status {
+        TestStatus::Passed => Color::Green,
+        TestStatus::Failed => Color::Red,
+        TestStatus::Skipped => Color::DarkGray,
+        TestStatus::XFailed => Color::Yellow,
+        TestStatus::XPassed => Color::Magenta,
+        TestStatus::Flaky => Color::Cyan,
+        TestStatus::NotRun => Color::DarkGray,
+        TestStatus::Error => Color::LightRed,
+        TestStatus::HookFailed => Color::LightMagenta,
+    }
+}
+
+pub fn render_ui(frame: &mut Frame, app: &App) {
     let size = frame.area();
 
     if app.show_help {
         // Show help overlay
-        render_help::<B>(frame, size);
+        render_help(frame, size);
     } else {
         // Main UI
         let main_chunks = Layout::default()
@@ -35,37 +66,38 @@ pub fn render_ui<B: Backend>(frame: &mut Frame, app: &App) {
             .split(size);
 
         // Title
-        render_title::<B>(frame, main_chunks[0]);
+        render_title(frame, main_chunks[0]);
 
         // Tabs
-        render_tabs::<B>(frame, main_chunks[1], app);
+        render_tabs(frame, main_chunks[1], app);
 
         // Content based on selected tab
         match app.tab_index {
-            0 => render_results_tab::<B>(frame, main_chunks[2], app),
-            1 => render_stats_tab::<B>(frame, main_chunks[2], app),
-            2 => render_diff_tab::<B>(frame, main_chunks[2], app),
-            3 => render_command_tab::<B>(frame, main_chunks[2], app),
-            4 => render_history_tab::<B>(frame, main_chunks[2], app),
+            0 => render_results_tab(frame, main_chunks[2], app),
+            1 => render_stats_tab(frame, main_chunks[2], app),
+            2 => render_diff_tab(frame, main_chunks[2], app),
+            3 => render_command_tab(frame, main_chunks[2], app),
+            4 => render_history_tab(frame, main_chunks[2], app),
+            5 => render_build_tab(frame, main_chunks[2], app),
             _ => {}
         }
 
         // Status bar
-        render_status_bar::<B>(frame, main_chunks[3]);
+        render_status_bar(frame, main_chunks[3], app);
 
         // ポップアップがあれば表示
         if app.show_popup {
-            render_popup::<B>(frame, size, app);
+            render_popup(frame, size, app);
         }
 
         // 結果ポップアップがあれば表示
         if app.result_popup_visible {
-            render_result_popup::<B>(frame, size, app);
+            render_result_popup(frame, size, app);
         }
     }
 }
 
-fn render_title<B: Backend>(frame: &mut Frame, area: Rect) {
+fn render_title(frame: &mut Frame, area: Rect) {
     let title = Paragraph::new(vec![
         TextLine::from(vec![
             Span::styled(
@@ -116,13 +148,14 @@ fn render_title<B: Backend>(frame: &mut Frame, area: Rect) {
     frame.render_widget(title, area);
 }
 
-fn render_tabs<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
-    let titles = vec![
+fn render_tabs(frame: &mut Frame, area: Rect, app: &App) {
+    let titles = [
         "Test Results",
         "Statistics",
         "Diff View",
         "Commands",
         "History",
+        "Build",
     ];
 
     // タブのスタイルを改善
@@ -164,7 +197,7 @@ fn render_tabs<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
     frame.render_widget(tabs, area);
 }
 
-fn render_results_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
+fn render_results_tab(frame: &mut Frame, area: Rect, app: &App) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
@@ -182,8 +215,8 @@ fn render_results_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
         .iter()
         .enumerate()
         .map(|(i, t)| {
-            let status_symbol = if t.success { "✓" } else { "✗" };
-            let status_bg = if t.success { Color::Green } else { Color::Red };
+            let status_symbol = status_symbol(t.status);
+            let status_bg = status_color(t.status);
 
             // テスト結果をより視覚的に分かりやすく
             let content = if i == app.selected_test {
@@ -233,7 +266,7 @@ fn render_results_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
     let tests_list = List::new(tests)
         .block(
             Block::default()
-                .title(" Tests ")
+                .title(tests_list_title(app))
                 .title_style(Style::default().fg(Color::Yellow))
                 .borders(Borders::ALL)
                 .border_type(BorderType::Rounded)
@@ -249,17 +282,53 @@ fn render_results_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
     frame.render_widget(tests_list, chunks[0]);
 
     // Test details area
-    if let Some(test_result) = app.test_results.get(app.selected_test) {
+    if let Some(test_result) = app.test_results.get(app.selected_test).filter(|t| {
+        matches!(
+            t.status,
+            TestStatus::Skipped | TestStatus::NotRun | TestStatus::Error | TestStatus::HookFailed
+        )
+    }) {
+        // 実行されなかったテストは出力がないので理由だけ表示
+        let skipped = Paragraph::new(format!(
+            "{} Test {}{}",
+            status_symbol(test_result.status),
+            match test_result.status {
+                TestStatus::Skipped => "skipped",
+                TestStatus::Error => "errored",
+                TestStatus::HookFailed => "hook failed",
+                _ => "not run",
+            },
+            test_result
+                .reason
+                .as_ref()
+                .map(|r| format!(": {}", r))
+                .unwrap_or_default()
+        ))
+        .style(Style::default().fg(Color::Gray))
+        .alignment(Alignment::Center)
+        .block(
+            Block::default()
+                .title(" Details ")
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded),
+        );
+
+        frame.render_widget(skipped, chunks[1]);
+    } else if let Some(test_result) = app.current_result() {
         let details_layout = Layout::default()
             .direction(Direction::Horizontal)
             .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
             .split(chunks[1]);
 
         // Expected output with fancy styling
-        let expected_title = format!(
-            " Expected Output {} ",
-            if test_result.success { "✓" } else { "≠" }
-        );
+        let expected_title = match test_result.status {
+            TestStatus::XFailed => " Expected Output ≠ (xfail: expected) ".to_string(),
+            TestStatus::XPassed => " Expected Output ✓ (xfail: unexpectedly passed!) ".to_string(),
+            _ => format!(
+                " Expected Output {} ",
+                if test_result.success { "✓" } else { "≠" }
+            ),
+        };
 
         let expected = Paragraph::new(if let Some(diff) = &test_result.diff {
             let expected_lines: Vec<TextLine> = diff
@@ -293,8 +362,9 @@ fn render_results_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
 
         // Actual output with fancy styling
         let actual_title = format!(
-            " Actual Output {} ",
-            if test_result.success { "✓" } else { "≠" }
+            " Actual Output {} {}",
+            if test_result.success { "✓" } else { "≠" },
+            attempt_label(app)
         );
 
         let actual = Paragraph::new(if let Some(diff) = &test_result.diff {
@@ -345,12 +415,13 @@ fn render_results_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
     }
 }
 
-fn render_stats_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
-    let (passed, total, pass_rate) = app.get_stats();
+fn render_stats_tab(frame: &mut Frame, area: Rect, app: &App) {
+    let stats = app.get_stats();
+    let pass_rate = stats.pass_rate();
 
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
         .split(area);
 
     // Summary stats in a fancy table
@@ -365,15 +436,52 @@ fn render_stats_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
     let rows = vec![
         Row::new(vec![
             Cell::from("Total Tests"),
-            Cell::from(total.to_string()),
+            Cell::from(stats.total.to_string()),
         ]),
         Row::new(vec![
             Cell::from("Passed Tests"),
-            Cell::from(passed.to_string()).style(Style::default().fg(Color::Green)),
+            Cell::from(stats.passed.to_string())
+                .style(Style::default().fg(status_color(TestStatus::Passed))),
         ]),
         Row::new(vec![
             Cell::from("Failed Tests"),
-            Cell::from((total - passed).to_string()).style(Style::default().fg(Color::Red)),
+            Cell::from(stats.failed.to_string())
+                .style(Style::default().fg(status_color(TestStatus::Failed))),
+        ]),
+        Row::new(vec![
+            Cell::from("Skipped Tests"),
+            Cell::from(stats.skipped.to_string())
+                .style(Style::default().fg(status_color(TestStatus::Skipped))),
+        ]),
+        Row::new(vec![
+            Cell::from("Expected Failures (xfail)"),
+            Cell::from(stats.xfailed.to_string())
+                .style(Style::default().fg(status_color(TestStatus::XFailed))),
+        ]),
+        Row::new(vec![
+            Cell::from("Unexpected Passes (xpass)"),
+            Cell::from(stats.xpassed.to_string())
+                .style(Style::default().fg(status_color(TestStatus::XPassed))),
+        ]),
+        Row::new(vec![
+            Cell::from("Flaky Tests"),
+            Cell::from(stats.flaky.to_string())
+                .style(Style::default().fg(status_color(TestStatus::Flaky))),
+        ]),
+        Row::new(vec![
+            Cell::from("Errors"),
+            Cell::from(stats.errors.to_string())
+                .style(Style::default().fg(status_color(TestStatus::Error))),
+        ]),
+        Row::new(vec![
+            Cell::from("Hook Failures"),
+            Cell::from(stats.hook_failures.to_string())
+                .style(Style::default().fg(status_color(TestStatus::HookFailed))),
+        ]),
+        Row::new(vec![
+            Cell::from("Not Run"),
+            Cell::from(stats.not_run.to_string())
+                .style(Style::default().fg(status_color(TestStatus::NotRun))),
         ]),
         Row::new(vec![
             Cell::from("Pass Rate"),
@@ -404,11 +512,7 @@ fn render_stats_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
     frame.render_widget(table, chunks[0]);
 
     // Visual chart of pass/fail ratio
-    let pass_percentage = if total > 0 {
-        passed as f64 / total as f64
-    } else {
-        0.0
-    };
+    let pass_percentage = pass_rate / 100.0;
 
     // Show a bar chart of pass/fail
     let canvas = Canvas::default()
@@ -465,9 +569,10 @@ fn render_stats_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
     frame.render_widget(canvas, chunks[1]);
 }
 
-fn render_diff_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
-    if let Some(test_result) = app.test_results.get(app.selected_test) {
-        if let Some(diff) = &test_result.diff {
+fn render_diff_tab(frame: &mut Frame, area: Rect, app: &App) {
+    if let Some(test_result) = app.current_result() {
+        let failed_checks = test_result.failed_checks();
+        if test_result.diff.is_some() || !failed_checks.is_empty() {
             // Create a unified diff view
             let mut diff_spans = Vec::new();
 
@@ -480,6 +585,10 @@ fn render_diff_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
                         .fg(Color::Yellow)
                         .add_modifier(Modifier::BOLD),
                 ),
+                Span::styled(
+                    format!(" {}", attempt_label(app)),
+                    Style::default().fg(Color::Cyan),
+                ),
             ]));
 
             diff_spans.push(TextLine::from(vec![Span::raw(
@@ -487,17 +596,18 @@ fn render_diff_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
             )]));
 
             // Add each diff line with appropriate styling
-            for line in diff {
-                let (prefix, style) = match line.tag {
-                    ChangeTag::Delete => ("-", Style::default().fg(Color::Red)),
-                    ChangeTag::Insert => ("+", Style::default().fg(Color::Green)),
-                    ChangeTag::Equal => (" ", Style::default()),
-                };
-
-                diff_spans.push(TextLine::from(vec![Span::styled(
-                    format!("{} {}", prefix, line.content),
-                    style,
-                )]));
+            if let Some(diff) = &test_result.diff {
+                if !failed_checks.is_empty() {
+                    diff_spans.push(file_heading("Output".to_string()));
+                }
+                push_diff_lines(&mut diff_spans, diff);
+            }
+
+            // expected_files のファイルごとに理由と差分を表示
+            for check in failed_checks {
+                diff_spans.push(TextLine::from(""));
+                diff_spans.push(file_heading(check.summary()));
+                push_diff_lines(&mut diff_spans, check.diff.unwrap_or_default());
             }
 
             let diff_view = Paragraph::new(diff_spans)
@@ -552,7 +662,31 @@ fn render_diff_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
     }
 }
 
-fn render_command_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
+fn file_heading(text: String) -> TextLine<'static> {
+    TextLine::from(Span::styled(
+        text,
+        Style::default()
+            .fg(Color::Yellow)
+            .add_modifier(Modifier::BOLD),
+    ))
+}
+
+fn push_diff_lines(lines: &mut Vec<TextLine<'_>>, diff: &[DiffLine]) {
+    for line in diff {
+        let (prefix, style) = match line.tag {
+            ChangeTag::Delete => ("-", Style::default().fg(Color::Red)),
+            ChangeTag::Insert => ("+", Style::default().fg(Color::Green)),
+            ChangeTag::Equal => (" ", Style::default()),
+        };
+
+        lines.push(TextLine::from(vec![Span::styled(
+            format!("{} {}", prefix, line.content),
+            style,
+        )]));
+    }
+}
+
+fn render_command_tab(frame: &mut Frame, area: Rect, app: &App) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
@@ -570,13 +704,11 @@ fn render_command_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
         .iter()
         .enumerate()
         .map(|(i, t)| {
-            let status_symbol = if t.success { "✓" } else { "✗" };
-
             let content = TextLine::from(vec![
                 Span::styled(
-                    format!(" {} ", status_symbol),
+                    format!(" {} ", status_symbol(t.status)),
                     Style::default()
-                        .fg(Color::Green)
+                        .fg(status_color(t.status))
                         .add_modifier(Modifier::BOLD),
                 ),
                 Span::styled(
@@ -601,7 +733,7 @@ fn render_command_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
     let tests_list = List::new(tests)
         .block(
             Block::default()
-                .title(" Tests ")
+                .title(tests_list_title(app))
                 .title_style(Style::default().fg(Color::Yellow))
                 .borders(Borders::ALL)
                 .border_type(BorderType::Rounded)
@@ -646,6 +778,36 @@ fn render_command_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
             ]),
         ];
 
+        // 再試行があった場合は各試行の結果と時間を表示
+        if let Some(result) = app.test_results.get(app.selected_test) {
+            if result.attempts.len() > 1 {
+                let attempts = result
+                    .attempts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, a)| {
+                        format!(
+                            "#{} {} {}ms",
+                            i + 1,
+                            if a.success { "pass" } else { "fail" },
+                            a.execution_time.as_millis()
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" | ");
+                rows.push(Row::new(vec![
+                    Cell::from("Attempts:"),
+                    Cell::from(attempts).style(Style::default().fg(Color::Cyan)),
+                ]));
+            }
+            if let Some(dir) = &result.temp_dir {
+                rows.push(Row::new(vec![
+                    Cell::from("Temp Dir (kept):"),
+                    Cell::from(dir.display().to_string()).style(Style::default().fg(Color::Cyan)),
+                ]));
+            }
+        }
+
         // Add build commands if available
         if let Some(commands) = build_commands {
             if !commands.is_empty() {
@@ -656,6 +818,7 @@ fn render_command_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
             }
         }
 
+        let table_height = rows.len() as u16 + 2;
         let command_table = Table::new(
             rows,
             &[Constraint::Percentage(30), Constraint::Percentage(70)],
@@ -674,8 +837,8 @@ fn render_command_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
             .direction(Direction::Vertical)
             .constraints(
                 [
-                    Constraint::Length(6), // Command table (increased height for build info)
-                    Constraint::Min(0),    // Input (if any)
+                    Constraint::Length(table_height), // Command table
+                    Constraint::Min(0),               // Input (if any)
                 ]
                 .as_ref(),
             )
@@ -698,6 +861,22 @@ fn render_command_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
 
             frame.render_widget(input_paragraph, details_chunks[1]);
         }
+
+        // 対話のテストでは、どこまで進んでどこで食い違ったかを表示
+        if let Some(result) = app.current_result().filter(|r| !r.dialog.is_empty()) {
+            let dialog_paragraph = Paragraph::new(dialog_lines(&result.dialog))
+                .block(
+                    Block::default()
+                        .title(" Dialog ")
+                        .title_style(Style::default().fg(Color::Yellow))
+                        .borders(Borders::ALL)
+                        .border_type(BorderType::Rounded)
+                        .border_style(Style::default().fg(Color::Blue)),
+                )
+                .wrap(Wrap { trim: false });
+
+            frame.render_widget(dialog_paragraph, details_chunks[1]);
+        }
     } else {
         // No test selected or no tests available
         let no_tests = Paragraph::new("No command details available")
@@ -714,7 +893,65 @@ fn render_command_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
     }
 }
 
-fn render_status_bar<B: Backend>(frame: &mut Frame, area: Rect) {
+// 対話の各ステップと、expect を待つ間に読んだ出力の行
+fn dialog_lines(events: &[DialogEvent]) -> Vec<TextLine<'static>> {
+    let mut lines = Vec::new();
+    for event in events {
+        if let Some(expect) = &event.expect {
+            let (mark, color) = if event.success {
+                ("✓", Color::Green)
+            } else {
+                ("✗", Color::Red)
+            };
+            lines.push(TextLine::from(Span::styled(
+                format!("{} expect /{}/", mark, expect),
+                Style::default().fg(color).add_modifier(Modifier::BOLD),
+            )));
+            for line in event.output.lines() {
+                lines.push(TextLine::from(Span::styled(
+                    format!("  │ {}", line),
+                    Style::default().fg(Color::Gray),
+                )));
+            }
+        }
+        if let Some(message) = &event.message {
+            lines.push(TextLine::from(Span::styled(
+                format!("  {}", message),
+                Style::default().fg(Color::Red),
+            )));
+        }
+        if let Some(send) = event.send.as_ref().filter(|_| event.success) {
+            lines.push(TextLine::from(Span::styled(
+                format!("→ send {:?}", send),
+                Style::default().fg(Color::Cyan),
+            )));
+        }
+    }
+    lines
+}
+
+fn render_status_bar(frame: &mut Frame, area: Rect, app: &App) {
+    // フィルタ入力中は入力欄として表示
+    if app.editing_filter {
+        let input = Paragraph::new(TextLine::from(vec![
+            Span::styled(
+                " Filter: ",
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!("{}█", app.filter_input)),
+            Span::styled(
+                "  (Enter: apply | Esc: clear)",
+                Style::default().fg(Color::Gray),
+            ),
+        ]))
+        .style(Style::default().bg(Color::DarkGray));
+
+        frame.render_widget(input, area);
+        return;
+    }
+
     let status_text = vec![
         Span::styled(
             "q",
@@ -754,6 +991,8 @@ fn render_status_bar<B: Backend>(frame: &mut Frame, area: Rect) {
                 .add_modifier(Modifier::BOLD),
         ),
         Span::raw(": history | "),
+        Span::styled("/", Style::default().fg(Color::Yellow)),
+        Span::raw(": filter | "),
         Span::styled(
             "?",
             Style::default()
@@ -770,7 +1009,7 @@ fn render_status_bar<B: Backend>(frame: &mut Frame, area: Rect) {
     frame.render_widget(status_bar, area);
 }
 
-fn render_help<B: Backend>(frame: &mut Frame, area: Rect) {
+fn render_help(frame: &mut Frame, area: Rect) {
     let help_area = centered_rect(60, 60, area);
 
     let help_text = vec![
@@ -822,6 +1061,19 @@ fn render_help<B: Backend>(frame: &mut Frame, area: Rect) {
             Span::styled("R", Style::default().fg(Color::Yellow)),
             Span::raw(": Run tests in release mode"),
         ]),
+        TextLine::from(vec![
+            Span::styled("u", Style::default().fg(Color::Yellow)),
+            Span::raw(": Accept the actual output of the selected test (bless)"),
+        ]),
+        TextLine::from(""),
+        TextLine::from(vec![
+            Span::styled("[ / ]", Style::default().fg(Color::Yellow)),
+            Span::raw(": Previous / next attempt of a retried test"),
+        ]),
+        TextLine::from(vec![
+            Span::styled("/", Style::default().fg(Color::Yellow)),
+            Span::raw(": Filter tests by name (substring or regex)"),
+        ]),
         TextLine::from(""),
         TextLine::from(vec![Span::styled(
             "History",
@@ -841,6 +1093,10 @@ fn render_help<B: Backend>(frame: &mut Frame, area: Rect) {
             Span::styled("p", Style::default().fg(Color::Yellow)),
             Span::raw(": Previous history entry (in history view)"),
         ]),
+        TextLine::from(vec![
+            Span::styled("↑/↓", Style::default().fg(Color::Yellow)),
+            Span::raw(": Select a build step or compiler message (in Build tab)"),
+        ]),
         TextLine::from(""),
         TextLine::from(vec![Span::styled(
             "General",
@@ -876,6 +1132,32 @@ fn render_help<B: Backend>(frame: &mut Frame, area: Rect) {
     frame.render_widget(help, help_area);
 }
 
+// 再試行されたテストで表示中の試行（例: "[attempt 1/3] "）
+fn attempt_label(app: &App) -> String {
+    match app.test_results.get(app.selected_test) {
+        Some(result) if result.attempts.len() > 1 => format!(
+            "[attempt {}/{} ([/] to switch)] ",
+            app.selected_attempt.unwrap_or(result.attempts.len() - 1) + 1,
+            result.attempts.len()
+        ),
+        _ => String::new(),
+    }
+}
+
+// フィルタが有効な場合はテスト一覧のタイトルに表示する
+fn tests_list_title(app: &App) -> String {
+    if app.filter.is_empty() {
+        " Tests ".to_string()
+    } else {
+        format!(
+            " Tests ({}/{}) filter: {} ",
+            app.test_results.len(),
+            app.all_results.len(),
+            app.filter.describe()
+        )
+    }
+}
+
 // ヘルプウィンドウを中央に表示するためのヘルパー関数
 pub fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
@@ -904,7 +1186,7 @@ pub fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
 }
 
 // 履歴表示モードのレンダリング
-fn render_history_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
+fn render_history_tab(frame: &mut Frame, area: Rect, app: &App) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
@@ -965,17 +1247,14 @@ fn render_history_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
     let rows: Vec<Row> = history_stats
         .iter()
         .enumerate()
-        .map(|(i, (timestamp, passed, total, is_release))| {
+        .map(|(i, (timestamp, stats, is_release))| {
             // Unix タイムスタンプを DateTime に変換
             let dt: DateTime<Utc> = Utc.timestamp_opt(*timestamp as i64, 0).unwrap();
             let formatted_time = dt.format("%Y-%m-%d %H:%M:%S").to_string();
 
             // 合格率を計算
-            let pass_rate = if *total > 0 {
-                (*passed as f64 / *total as f64) * 100.0
-            } else {
-                0.0
-            };
+            let pass_rate = stats.pass_rate();
+            let (passed, total) = (stats.ok(), stats.executed());
 
             // 合格率に応じた色を設定
             let pass_rate_style = if pass_rate > 90.0 {
@@ -1001,9 +1280,9 @@ fn render_history_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
                 Cell::from(formatted_time).style(row_style),
                 Cell::from(format!("{}/{}", passed, total)).style(if i == app.selected_history {
                     row_style
-                } else if *passed == *total {
+                } else if passed == total {
                     Style::default().fg(Color::Green)
-                } else if *passed > 0 {
+                } else if passed > 0 {
                     Style::default().fg(Color::Yellow)
                 } else {
                     Style::default().fg(Color::Red)
@@ -1061,9 +1340,197 @@ fn render_history_tab<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
     frame.render_widget(history_table, chunks[1]);
 }
 
+// ビルド出力の表示（ステップと診断の一覧、選択中の項目の詳細）
+fn render_build_tab(frame: &mut Frame, area: Rect, app: &App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Percentage(40), // ステップと診断の一覧
+                Constraint::Min(0),         // 詳細
+            ]
+            .as_ref(),
+        )
+        .split(area);
+
+    if app.build_steps.is_empty() {
+        let message = Paragraph::new(
+            "No build steps in the last run.\n\
+             Pre-build commands and cargo_bin builds are listed here.",
+        )
+        .style(Style::default().fg(Color::Gray))
+        .alignment(Alignment::Center)
+        .block(
+            Block::default()
+                .title(" Build ")
+                .title_style(Style::default().fg(Color::Yellow))
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded),
+        );
+        frame.render_widget(message, area);
+        return;
+    }
+
+    let entries = app.build_entries();
+    let items: Vec<ListItem> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let step = &app.build_steps[entry.step];
+            let line = match entry.diagnostic {
+                None => {
+                    let errors = step.errors().count();
+                    let warnings = step.diagnostics.len() - errors;
+                    let mut spans = vec![
+                        Span::styled(
+                            if step.success { "✓ " } else { "✗ " },
+                            Style::default().fg(if step.success {
+                                Color::Green
+                            } else {
+                                Color::Red
+                            }),
+                        ),
+                        Span::styled(
+                            if step.cached {
+                                format!("{:>8} ", "cached")
+                            } else {
+                                format!("{:>7.2}s ", step.duration.as_secs_f64())
+                            },
+                            Style::default().fg(Color::Gray),
+                        ),
+                        Span::styled(
+                            match (&step.test, &step.name) {
+                                (Some(test), Some(name)) => format!("[{}/{}] ", test, name),
+                                (Some(label), None) | (None, Some(label)) => {
+                                    format!("[{}] ", label)
+                                }
+                                (None, None) => "[global] ".to_string(),
+                            },
+                            Style::default().fg(Color::Cyan),
+                        ),
+                        Span::raw(step.command.clone()),
+                    ];
+                    if errors > 0 {
+                        spans.push(Span::styled(
+                            format!("  {} error(s)", errors),
+                            Style::default().fg(Color::Red),
+                        ));
+                    }
+                    if warnings > 0 {
+                        spans.push(Span::styled(
+                            format!("  {} warning(s)", warnings),
+                            Style::default().fg(Color::Yellow),
+                        ));
+                    }
+                    TextLine::from(spans)
+                }
+                Some(d) => {
+                    let diagnostic = &step.diagnostics[d];
+                    let location = match (&diagnostic.file, diagnostic.line, diagnostic.column) {
+                        (Some(file), Some(line), Some(column)) => {
+                            format!("{}:{}:{} ", file, line, column)
+                        }
+                        (Some(file), _, _) => format!("{} ", file),
+                        _ => String::new(),
+                    };
+                    TextLine::from(vec![
+                        Span::raw("    "),
+                        Span::styled(
+                            format!("{} ", diagnostic.level),
+                            Style::default().fg(if diagnostic.level == "error" {
+                                Color::Red
+                            } else {
+                                Color::Yellow
+                            }),
+                        ),
+                        Span::styled(location, Style::default().fg(Color::Blue)),
+                        Span::raw(diagnostic.message.clone()),
+                    ])
+                }
+            };
+            let style = if i == app.selected_build {
+                Style::default()
+                    .bg(Color::DarkGray)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            ListItem::new(line).style(style)
+        })
+        .collect();
+
+    // 選択中の項目が見えるように先頭をずらす
+    let visible = chunks[0].height.saturating_sub(2) as usize;
+    let offset = app.selected_build.saturating_sub(visible.saturating_sub(1));
+    let list = List::new(items.into_iter().skip(offset).collect::<Vec<_>>()).block(
+        Block::default()
+            .title(" Build Steps (↑/k ↓/j to select) ")
+            .title_style(Style::default().fg(Color::Yellow))
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Blue)),
+    );
+    frame.render_widget(list, chunks[0]);
+
+    // 診断を選んでいればその全文、ステップならその出力を表示する
+    let Some(entry) = entries.get(app.selected_build) else {
+        return;
+    };
+    let step = &app.build_steps[entry.step];
+    let (title, text) = match entry.diagnostic {
+        Some(d) => (
+            " Diagnostic ".to_string(),
+            step.diagnostics[d].rendered.trim_end().to_string(),
+        ),
+        None => {
+            let mut text = String::new();
+            if !step.stdout.trim().is_empty() {
+                text.push_str("--- stdout ---\n");
+                text.push_str(step.stdout.trim_end());
+                text.push('\n');
+            }
+            if !step.stderr.trim().is_empty() {
+                text.push_str("--- stderr ---\n");
+                text.push_str(step.stderr.trim_end());
+            }
+            if step.cached {
+                text.push_str("(not run: its inputs did not change since its last success)");
+            } else if text.is_empty() {
+                text.push_str("(no output)");
+            }
+            (format!(" Output: {} ", step.command), text)
+        }
+    };
+    // 長い出力は末尾（エラーが出る辺り）を表示する
+    let height = chunks[1].height.saturating_sub(2) as usize;
+    let lines: Vec<TextLine> = text
+        .lines()
+        .map(|l| TextLine::from(l.to_string()))
+        .collect();
+    let scroll = if entry.diagnostic.is_none() {
+        lines.len().saturating_sub(height)
+    } else {
+        0
+    };
+    let detail = Paragraph::new(lines).scroll((scroll as u16, 0)).block(
+        Block::default()
+            .title(title)
+            .title_style(Style::default().fg(Color::Yellow))
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Green)),
+    );
+    frame.render_widget(detail, chunks[1]);
+}
+
 // ポップアップを表示する関数
-fn render_popup<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
-    let popup_area = centered_rect(50, 30, area);
+fn render_popup(frame: &mut Frame, area: Rect, app: &App) {
+    // 差分を見せるので bless の確認は大きく表示する
+    let popup_area = if app.popup_type == PopupType::Bless {
+        centered_rect(70, 60, area)
+    } else {
+        centered_rect(50, 30, area)
+    };
 
     // 背景を描画（完全な黒）
     let background = Block::default().style(Style::default().bg(Color::Black));
@@ -1073,6 +1540,7 @@ fn render_popup<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
         PopupType::RunTests => " Run Tests ",
         PopupType::RunRelease => " Run Tests in Release Mode ",
         PopupType::BuildToggle => " Toggle Build Mode ",
+        PopupType::Bless => " Bless Test ",
         PopupType::None => "",
         PopupType::ResultNotification => " Test Results ",
     };
@@ -1138,7 +1606,7 @@ fn render_popup<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
         PopupType::BuildToggle => vec![
             TextLine::from(""),
             TextLine::from(vec![Span::styled(
-                if app.config.build.as_ref().map_or(false, |b| b.release) {
+                if app.config.build.as_ref().is_some_and(|b| b.release) {
                     "Switch to DEBUG mode?"
                 } else {
                     "Switch to RELEASE mode?"
@@ -1148,7 +1616,7 @@ fn render_popup<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
                     .add_modifier(Modifier::BOLD),
             )]),
             TextLine::from(""),
-            TextLine::from(if app.config.build.as_ref().map_or(false, |b| b.release) {
+            TextLine::from(if app.config.build.as_ref().is_some_and(|b| b.release) {
                 "This will switch to debug mode for the next test run."
             } else {
                 "This will switch to release mode for the next test run."
@@ -1157,12 +1625,12 @@ fn render_popup<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
             TextLine::from(vec![
                 Span::styled("Current mode: ", Style::default().fg(Color::Gray)),
                 Span::styled(
-                    if app.config.build.as_ref().map_or(false, |b| b.release) {
+                    if app.config.build.as_ref().is_some_and(|b| b.release) {
                         "RELEASE"
                     } else {
                         "DEBUG"
                     },
-                    if app.config.build.as_ref().map_or(false, |b| b.release) {
+                    if app.config.build.as_ref().is_some_and(|b| b.release) {
                         Style::default()
                             .fg(Color::Magenta)
                             .add_modifier(Modifier::BOLD)
@@ -1190,6 +1658,7 @@ fn render_popup<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
                 Span::styled(" to cancel", Style::default().fg(Color::Gray)),
             ]),
         ],
+        PopupType::Bless => render_bless_message(app),
         PopupType::ResultNotification => vec![
             TextLine::from(""),
             TextLine::from(vec![Span::styled(
@@ -1229,14 +1698,71 @@ fn render_popup<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
 
     let popup = Paragraph::new(popup_message)
         .block(popup_block)
-        .alignment(Alignment::Center)
+        .alignment(if app.popup_type == PopupType::Bless {
+            Alignment::Left
+        } else {
+            Alignment::Center
+        })
         .style(Style::default().fg(Color::White)); // テキストを白に設定
 
     frame.render_widget(popup, popup_area);
 }
 
+// bless の確認メッセージ（書き込み先と差分）
+fn render_bless_message(app: &App) -> Vec<TextLine<'static>> {
+    let Some(update) = &app.pending_bless else {
+        return vec![];
+    };
+
+    let mut lines = vec![
+        TextLine::from(""),
+        TextLine::from(vec![Span::styled(
+            format!(" Accept the actual output of {} as expected?", update.name),
+            Style::default()
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )]),
+        TextLine::from(format!(" Writes to: {}", update.target.path().display())),
+        TextLine::from(""),
+    ];
+
+    let diff = app
+        .test_results
+        .get(app.selected_test)
+        .and_then(|r| r.diff.as_ref());
+    for line in diff.into_iter().flatten() {
+        let (prefix, style) = match line.tag {
+            ChangeTag::Delete => ("-", Style::default().fg(Color::Red)),
+            ChangeTag::Insert => ("+", Style::default().fg(Color::Green)),
+            ChangeTag::Equal => (" ", Style::default()),
+        };
+        lines.push(TextLine::from(vec![Span::styled(
+            format!(" {} {}", prefix, line.content.trim_end_matches('\n')),
+            style,
+        )]));
+    }
+
+    lines.push(TextLine::from(""));
+    lines.push(TextLine::from(vec![
+        Span::styled(" Press ", Style::default().fg(Color::Gray)),
+        Span::styled(
+            "Enter",
+            Style::default()
+                .fg(Color::Green)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(" to write it or ", Style::default().fg(Color::Gray)),
+        Span::styled(
+            "Esc",
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(" to cancel", Style::default().fg(Color::Gray)),
+    ]));
+    lines
+}
+
 // 結果ポップアップを表示する関数
-fn render_result_popup<B: Backend>(frame: &mut Frame, area: Rect, app: &App) {
+fn render_result_popup(frame: &mut Frame, area: Rect, app: &App) {
     let popup_area = centered_rect(40, 20, area);
 
     // 背景を描画（完全な黒）
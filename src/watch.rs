@@ -0,0 +1,362 @@
+// src/watch.rs
+use crate::test::{self, TestConfig, TestResult, TestStatus};
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use notify::{EventKind, RecursiveMode, Watcher as _};
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+
+/// How long the files must stay untouched before a rerun starts
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Options of `--watch`
+#[derive(Debug, Clone, Default)]
+pub struct WatchOptions {
+    /// Source globs from the command line, added to the `watch` globs of the config
+    pub globs: Vec<String>,
+    /// After a change, run the affected and previously failed tests alone first
+    pub affected_first: bool,
+}
+
+/// Watches the config file, the fixture files of the tests and the source globs
+pub struct Watcher {
+    watcher: notify::RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    cwd: PathBuf,
+    /// Directories currently registered with `watcher`
+    watched_dirs: Vec<(PathBuf, RecursiveMode)>,
+    files: HashSet<PathBuf>,
+    globs: GlobSet,
+    /// Changes seen since the last rerun, and when the last one happened
+    pending: BTreeSet<PathBuf>,
+    last_change: Option<Instant>,
+}
+
+impl Watcher {
+    pub fn new(
+        config_path: &Path,
+        config: Option<&TestConfig>,
+        options: &WatchOptions,
+    ) -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let watcher =
+            notify::recommended_watcher(sender).context("Failed to start the file watcher")?;
+        let cwd = std::env::current_dir()?.canonicalize()?;
+
+        let mut watcher = Watcher {
+            watcher,
+            events,
+            cwd,
+            watched_dirs: Vec::new(),
+            files: HashSet::new(),
+            globs: GlobSet::empty(),
+            pending: BTreeSet::new(),
+            last_change: None,
+        };
+        watcher.update(config_path, config, options)?;
+        Ok(watcher)
+    }
+
+    /// Re-register the watched paths, e.g. after the configuration was reloaded
+    pub fn update(
+        &mut self,
+        config_path: &Path,
+        config: Option<&TestConfig>,
+        options: &WatchOptions,
+    ) -> Result<()> {
+        let mut files = vec![config_path.to_path_buf()];
+        let mut globs = options.globs.clone();
+        if let Some(config) = config {
+            for test_case in &config.tests {
+                files.extend(test::fixture_files(test_case).into_iter().cloned());
+            }
+            globs.extend(config.watch.iter().flatten().cloned());
+        }
+
+        let mut dirs: Vec<(PathBuf, RecursiveMode)> = Vec::new();
+        // エディタはファイルを置き換えて保存することがあるので、親ディレクトリを監視する
+        for file in &files {
+            let path = self.absolute(file);
+            if let Some(parent) = path.parent() {
+                dirs.push((parent.to_path_buf(), RecursiveMode::NonRecursive));
+            }
+        }
+
+        let mut builder = GlobSetBuilder::new();
+        for glob in &globs {
+            builder.add(Glob::new(glob).with_context(|| format!("Invalid watch glob: {}", glob))?);
+            dirs.push((self.absolute(&glob_base(glob)), RecursiveMode::Recursive));
+        }
+        self.globs = builder.build()?;
+        self.files = files.iter().map(|f| self.absolute(f)).collect();
+
+        // 同じディレクトリは一度だけ、再帰的な監視を優先して登録する
+        dirs.sort_by_key(|(dir, mode)| (dir.clone(), *mode != RecursiveMode::Recursive));
+        dirs.dedup_by(|a, b| a.0 == b.0);
+        dirs.retain(|(dir, _)| dir.is_dir());
+
+        for (dir, _) in std::mem::take(&mut self.watched_dirs) {
+            let _ = self.watcher.unwatch(&dir);
+        }
+        for (dir, mode) in &dirs {
+            self.watcher
+                .watch(dir, *mode)
+                .with_context(|| format!("Failed to watch {}", dir.display()))?;
+        }
+        self.watched_dirs = dirs;
+
+        Ok(())
+    }
+
+    /// The changed files, once changes have settled; never blocks
+    pub fn poll(&mut self) -> Option<Vec<PathBuf>> {
+        while let Ok(event) = self.events.try_recv() {
+            self.record(event);
+        }
+        self.take_settled()
+    }
+
+    /// Block until files change and the changes have settled
+    pub fn wait(&mut self) -> Result<Vec<PathBuf>> {
+        loop {
+            match self.events.recv_timeout(Duration::from_millis(100)) {
+                Ok(event) => self.record(event),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(anyhow::anyhow!("The file watcher stopped"))
+                }
+            }
+            if let Some(changed) = self.take_settled() {
+                return Ok(changed);
+            }
+        }
+    }
+
+    fn record(&mut self, event: notify::Result<notify::Event>) {
+        let Ok(event) = event else {
+            return;
+        };
+        if !matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) {
+            return;
+        }
+
+        for path in event.paths {
+            if self.is_watched(&path) {
+                self.pending.insert(self.relative(&path));
+                self.last_change = Some(Instant::now());
+            }
+        }
+    }
+
+    fn take_settled(&mut self) -> Option<Vec<PathBuf>> {
+        let settled = self.last_change.is_some_and(|t| t.elapsed() >= DEBOUNCE);
+        if !settled {
+            return None;
+        }
+        self.last_change = None;
+        Some(std::mem::take(&mut self.pending).into_iter().collect())
+    }
+
+    fn is_watched(&self, path: &Path) -> bool {
+        self.files.contains(path) || self.globs.is_match(self.relative(path))
+    }
+
+    fn absolute(&self, path: &Path) -> PathBuf {
+        let path = self.cwd.join(path);
+        // 存在しないファイルは正規化できないので、親ディレクトリだけ正規化する
+        path.canonicalize().unwrap_or_else(|_| {
+            match (
+                path.parent().and_then(|p| p.canonicalize().ok()),
+                path.file_name(),
+            ) {
+                (Some(parent), Some(name)) => parent.join(name),
+                _ => path,
+            }
+        })
+    }
+
+    fn relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.cwd).unwrap_or(path).to_path_buf()
+    }
+}
+
+// グロブの中でワイルドカードを含まない先頭部分（監視するディレクトリ）
+pub(crate) fn glob_base(glob: &str) -> PathBuf {
+    let mut base = PathBuf::new();
+    for component in Path::new(glob).components() {
+        let part = component.as_os_str().to_string_lossy();
+        if part.contains(['*', '?', '[', '{']) {
+            break;
+        }
+        base.push(component);
+    }
+    // ワイルドカードがない場合はファイルそのものなので親ディレクトリにする
+    if base == Path::new(glob) {
+        base.pop();
+    }
+    base
+}
+
+/// Names of the tests to run first after a change: the tests whose definition or fixture
+/// files changed, then the tests that failed last time
+pub fn affected_tests(
+    config: &TestConfig,
+    previous_config: Option<&TestConfig>,
+    changed: &[PathBuf],
+    failed: &HashSet<String>,
+) -> Vec<String> {
+    let definition_changed = |test_case: &test::TestCase| {
+        let Some(previous) = previous_config else {
+            return false;
+        };
+        // 設定ファイル上の定義が変わったかどうかを直列化して比べる
+        let before = previous
+            .tests
+            .iter()
+            .find(|t| t.name == test_case.name)
+            .and_then(|t| serde_yaml::to_string(t).ok());
+        before != serde_yaml::to_string(test_case).ok()
+    };
+    let fixture_changed = |test_case: &test::TestCase| {
+        test::fixture_files(test_case)
+            .iter()
+            .any(|file| changed.iter().any(|c| c == *file || c.ends_with(file)))
+    };
+
+    let mut names: Vec<String> = config
+        .tests
+        .iter()
+        .filter(|t| definition_changed(t) || fixture_changed(t))
+        .map(|t| t.name.clone())
+        .collect();
+    for test_case in &config.tests {
+        if failed.contains(&test_case.name) && !names.contains(&test_case.name) {
+            names.push(test_case.name.clone());
+        }
+    }
+    names
+}
+
+/// Keep track of the tests that failed in their latest run
+pub fn update_failed(failed: &mut HashSet<String>, results: &[TestResult]) {
+    for result in results {
+        match result.status {
+            status if status.is_failure() => {
+                failed.insert(result.name.clone());
+            }
+            // 実行されなかったテストは前回の状態を保つ
+            TestStatus::Skipped | TestStatus::NotRun => {}
+            _ => {
+                failed.remove(&result.name);
+            }
+        }
+    }
+}
+
+/// The configuration restricted to the given tests, in the given order
+pub fn subset(config: &TestConfig, names: &[String]) -> TestConfig {
+    let mut subset = config.clone();
+    subset.tests = names
+        .iter()
+        .filter_map(|name| config.tests.iter().find(|t| &t.name == name).cloned())
+        .collect();
+    subset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> TestConfig {
+        serde_yaml::from_str(yaml).expect("valid test configuration")
+    }
+
+    const TESTS: &str = r#"
+tests:
+  - name: "a"
+    command: "echo"
+    expected_output: "a"
+  - name: "b"
+    command: "cat"
+    input_file: "fixtures/b.txt"
+    expected_output_file: "fixtures/b.out"
+  - name: "c"
+    command: "echo"
+    expected_output: "c"
+"#;
+
+    #[test]
+    fn glob_base_stops_at_the_first_wildcard() {
+        assert_eq!(glob_base("src/**/*.rs"), PathBuf::from("src"));
+        assert_eq!(glob_base("tests/case-?/in.txt"), PathBuf::from("tests"));
+        assert_eq!(glob_base("{src,lib}/*.rs"), PathBuf::new());
+        assert_eq!(glob_base("*.toml"), PathBuf::new());
+        // ワイルドカードのないパスはその親ディレクトリを監視する
+        assert_eq!(glob_base("src/main.rs"), PathBuf::from("src"));
+        assert_eq!(glob_base("Cargo.toml"), PathBuf::new());
+    }
+
+    #[test]
+    fn tests_with_changed_fixtures_are_affected() {
+        let config = config(TESTS);
+        let changed = [PathBuf::from("/work/project/fixtures/b.out")];
+
+        assert_eq!(
+            affected_tests(&config, Some(&config), &changed, &HashSet::new()),
+            ["b"]
+        );
+        assert!(affected_tests(
+            &config,
+            Some(&config),
+            &[PathBuf::from("src/main.rs")],
+            &HashSet::new()
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn changed_and_new_definitions_are_affected() {
+        let previous = config(TESTS);
+        let config = config(&format!(
+            "{}{}",
+            TESTS.replace("expected_output: \"c\"", "expected_output: \"C\""),
+            r#"
+  - name: "d"
+    command: "echo"
+    expected_output: "d"
+"#
+        ));
+
+        assert_eq!(
+            affected_tests(&config, Some(&previous), &[], &HashSet::new()),
+            ["c", "d"]
+        );
+        // 前回の設定がなければ定義の変更は分からない
+        assert!(affected_tests(&config, None, &[], &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn failed_tests_come_after_the_affected_ones() {
+        let config = config(TESTS);
+        let failed: HashSet<String> = ["a".to_string(), "b".to_string(), "gone".to_string()]
+            .into_iter()
+            .collect();
+
+        assert_eq!(
+            affected_tests(
+                &config,
+                Some(&config),
+                &[PathBuf::from("fixtures/b.txt")],
+                &failed
+            ),
+            ["b", "a"]
+        );
+    }
+}